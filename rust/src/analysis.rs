@@ -0,0 +1,301 @@
+use crate::error::AppError;
+use bitcoincore_rpc::bitcoin::{Amount, SignedAmount};
+use serde_json::Value;
+
+// Effective fee rate in sat/vB: what the transaction paid per virtual byte of block space.
+// vsize is weight / 4 rounded up, so witness bytes count for a quarter of non-witness bytes.
+pub fn effective_fee_rate(fee: Amount, vsize: u64) -> f64 {
+    if vsize == 0 {
+        return 0.0;
+    }
+    fee.to_sat() as f64 / vsize as f64
+}
+
+// A fee expressed in the units people mix up: BTC, satoshis and sat/vB
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeUnits {
+    pub btc: Amount,
+    pub sats: u64,
+    pub sat_per_vb: f64,
+}
+
+pub fn fee_in_units(fee: Amount, vsize: u64) -> FeeUnits {
+    FeeUnits {
+        btc: fee,
+        sats: fee.to_sat(),
+        sat_per_vb: effective_fee_rate(fee, vsize),
+    }
+}
+
+// BIP125: a transaction signals replaceability if any input has nSequence below 0xfffffffe
+pub fn signals_rbf(vin: &[Value]) -> bool {
+    vin.iter()
+        .filter_map(|input| input["sequence"].as_u64())
+        .any(|sequence| sequence < 0xffff_fffe)
+}
+
+// Hex payload of the first OP_RETURN (`nulldata`) output, if the transaction carries one.
+// The asm form is "OP_RETURN <push> [<push>...]"; the pushes are concatenated.
+pub fn op_return_data(vout: &[Value]) -> Option<String> {
+    let script = vout
+        .iter()
+        .map(|out| &out["scriptPubKey"])
+        .find(|spk| spk["type"].as_str() == Some("nulldata"))?;
+    let asm = script["asm"].as_str()?;
+    Some(asm.split_whitespace().skip(1).collect())
+}
+
+// Smallest output value Core will relay for a given output type, in sats.
+// Core's rule is: an output is dust if spending it would cost more than it's worth at the
+// dust relay fee (3 sat/vB), counting the output's own size plus the input that spends it.
+pub fn dust_limit(address_type: &str) -> Amount {
+    let sats = match address_type {
+        "p2pkh" => 546,          // (34 + 148) * 3
+        "p2sh" => 540,           // (32 + 148) * 3
+        "p2wpkh" => 294,         // (31 + 67) * 3
+        "p2wsh" | "p2tr" => 330, // (43 + 67) * 3
+        _ => 546,                // unknown types: assume the strictest legacy limit
+    };
+    Amount::from_sat(sats)
+}
+
+// Fail early, with an explanation, if `amount` would be rejected by the node as dust
+pub fn check_dust(amount: Amount, address_type: &str) -> Result<(), AppError> {
+    let limit = dust_limit(address_type);
+    if amount < limit {
+        return Err(AppError::Check(format!(
+            "{} sats is below the dust limit of {} sats for a {address_type} output; \
+             the node would refuse to relay it",
+            amount.to_sat(),
+            limit.to_sat()
+        )));
+    }
+    Ok(())
+}
+
+// Total value going in and coming out of a transaction: the `value`s of the outputs its inputs
+// spend (`prevouts`, as decoded outputs) and of its own decoded `vout`
+pub fn value_totals(prevouts: &[Value], vout: &[Value]) -> Result<(Amount, Amount), AppError> {
+    let sum = |outputs: &[Value]| -> Result<Amount, AppError> {
+        let mut total = Amount::ZERO;
+        for out in outputs {
+            total += Amount::from_btc(out["value"].as_f64().unwrap_or(0.0))?;
+        }
+        Ok(total)
+    };
+    Ok((sum(prevouts)?, sum(vout)?))
+}
+
+// The fundamental transaction invariant: everything going in either comes out again or is fee.
+// A mismatch here means we mis-parsed an input or output.
+pub fn verify_value_balance(
+    input_total: Amount,
+    outputs_total: Amount,
+    fee: Amount,
+) -> Result<(), AppError> {
+    if outputs_total.checked_add(fee) != Some(input_total) {
+        return Err(AppError::Check(format!(
+            "inputs ({} BTC) != outputs ({} BTC) + fee ({} BTC)",
+            input_total.to_btc(),
+            outputs_total.to_btc(),
+            fee.to_btc()
+        )));
+    }
+    Ok(())
+}
+
+// How a transaction changes the UTXO set: (outputs it spends, outputs it creates). A coinbase
+// input spends nothing, and OP_RETURN outputs are provably unspendable so never enter the set.
+pub fn utxo_impact(vin: &[Value], vout: &[Value]) -> (u64, u64) {
+    let spent = vin
+        .iter()
+        .filter(|input| input.get("coinbase").is_none())
+        .count();
+    let created = vout
+        .iter()
+        .filter(|out| out["scriptPubKey"]["type"].as_str() != Some("nulldata"))
+        .count();
+    (spent as u64, created as u64)
+}
+
+// Is this a segwit transaction (any input carries witness data)?
+pub fn is_segwit(vin: &[Value]) -> bool {
+    vin.iter().any(|input| {
+        input["txinwitness"]
+            .as_array()
+            .is_some_and(|witness| !witness.is_empty())
+    })
+}
+
+// How many vbytes the witness discount saves: without segwit every byte would cost a full
+// vbyte, so the saving is the serialized size minus the virtual size. Zero for legacy txs.
+pub fn witness_discount_vbytes(size: u64, vsize: u64) -> u64 {
+    size.saturating_sub(vsize)
+}
+
+// Fraction of the input value returned as change; zero with no change output (or no input)
+pub fn change_ratio(change: Amount, input_total: Amount) -> f64 {
+    if input_total == Amount::ZERO {
+        return 0.0;
+    }
+    change.to_sat() as f64 / input_total.to_sat() as f64
+}
+
+// Height at which a coinbase output becomes spendable: its block height plus the `maturity`
+// window (100 blocks on standard chains). `input_tx` is the `gettransaction` result (with
+// `decoded`) for the funding transaction; returns None if it isn't a coinbase or isn't
+// confirmed.
+pub fn coinbase_maturity_height(input_tx: &Value, maturity: u32) -> Option<u64> {
+    input_tx["decoded"]["vin"][0].get("coinbase")?;
+    let height = input_tx["blockheight"].as_u64()?;
+    Some(height + maturity as u64)
+}
+
+// Which of our wallets hold the key for an output's address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ownership {
+    pub trader: bool,
+    pub miner: bool,
+}
+
+// Output indices of the payment to the Trader and the Miner's change
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputRoles {
+    pub payment: Option<usize>,
+    pub change: Option<usize>,
+}
+
+// Work out which output is the payment and which is change from wallet ownership (one entry
+// per output) rather than by comparing address strings, so two outputs with look-alike or even
+// identical addresses are still told apart by index. The payment is the Trader-owned output
+// carrying `amount` (or the first Trader-owned one); change is a different output the Miner
+// owns, falling back to any other output with an address when the change was sent elsewhere.
+pub fn assign_output_roles(vout: &[Value], ownership: &[Ownership], amount: Amount) -> OutputRoles {
+    let value_of = |i: usize| {
+        vout[i]["value"]
+            .as_f64()
+            .and_then(|btc| Amount::from_btc(btc).ok())
+    };
+    let trader_owned = || (0..vout.len()).filter(|&i| ownership.get(i).is_some_and(|o| o.trader));
+    let payment = trader_owned()
+        .find(|&i| value_of(i) == Some(amount))
+        .or_else(|| trader_owned().next());
+    let others = || (0..vout.len()).filter(move |&i| Some(i) != payment);
+    let change = others()
+        .find(|&i| ownership.get(i).is_some_and(|o| o.miner))
+        .or_else(|| others().find(|&i| vout[i]["scriptPubKey"].get("address").is_some()));
+    OutputRoles { payment, change }
+}
+
+// The Miner's bottom line for the run: what it mined, less what it paid the Trader and the fee.
+// The fee comes back to the Miner in the confirming block's coinbase, so it shows up in `mined`
+// too. Signed because a run that spends older coins can end up negative. Any real BTC amount
+// (at most 21M BTC) fits in a SignedAmount, so the conversions can't overflow.
+pub fn net_position(mined: Amount, sent: Amount, fee: Amount) -> SignedAmount {
+    let signed = |amount: Amount| SignedAmount::from_sat(amount.to_sat() as i64);
+    signed(mined) - signed(sent) - signed(fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn output(value: f64, address: &str) -> Value {
+        json!({
+            "value": value,
+            "scriptPubKey": { "address": address, "type": "witness_v0_keyhash" }
+        })
+    }
+
+    #[test]
+    fn value_totals_differ_by_the_fee() {
+        // A 50 BTC coinbase output paying 20 BTC to the Trader, with change and a 141 sat fee
+        let prevouts = [output(50.0, "bcrt1q7f0pjwhc3jzzv0w4uurm589506glv2dggfan4s")];
+        let vout = [
+            output(20.0, "bcrt1qgxhen7fr637av4p9qthrqeca0pwjthuq3auec3"),
+            output(29.99999859, "bcrt1q4mecjzu9uc7wavhk6k2j92nnudvt8lpu6h4ed0"),
+        ];
+        let (input_total, outputs_total) = value_totals(&prevouts, &vout).unwrap();
+        assert_eq!(input_total, Amount::from_int_btc(50));
+        assert_eq!(outputs_total, Amount::from_sat(4_999_999_859));
+        assert_eq!(input_total - outputs_total, Amount::from_sat(141));
+        verify_value_balance(input_total, outputs_total, Amount::from_sat(141)).unwrap();
+    }
+
+    #[test]
+    fn effective_fee_rate_divides_fee_by_vsize() {
+        assert_eq!(effective_fee_rate(Amount::from_sat(1410), 141), 10.0);
+        assert_eq!(effective_fee_rate(Amount::from_sat(2820), 141), 20.0);
+        assert_eq!(effective_fee_rate(Amount::from_sat(1000), 0), 0.0);
+    }
+
+    #[test]
+    fn fee_in_units_converts_to_btc_sats_and_rate() {
+        let units = fee_in_units(Amount::from_sat(1_410), 141);
+        assert_eq!(units.btc.to_btc(), 0.0000141);
+        assert_eq!(units.sats, 1_410);
+        assert_eq!(units.sat_per_vb, 10.0);
+
+        // Without a size there is no rate, but the amount still converts
+        let units = fee_in_units(Amount::from_sat(1_410), 0);
+        assert_eq!(units.sats, 1_410);
+        assert_eq!(units.sat_per_vb, 0.0);
+    }
+
+    #[test]
+    fn value_balance_holds_when_outputs_plus_fee_equal_inputs() {
+        let inputs = Amount::from_int_btc(50);
+        let outputs = Amount::from_sat(4_999_998_590);
+        let fee = Amount::from_sat(1_410);
+        assert!(verify_value_balance(inputs, outputs, fee).is_ok());
+    }
+
+    #[test]
+    fn value_balance_rejects_mismatch() {
+        let inputs = Amount::from_int_btc(50);
+        let outputs = Amount::from_int_btc(49);
+        let fee = Amount::from_sat(1_410);
+        assert!(matches!(
+            verify_value_balance(inputs, outputs, fee),
+            Err(AppError::Check(_))
+        ));
+    }
+
+    #[test]
+    fn output_roles_follow_ownership_not_address_type() {
+        // Both outputs are P2WPKH, and Core happened to put the change first
+        let vout = [
+            output(29.9999859, "bcrt1qmr7r5zgxw5egu0gqmm5lj5wlgx4fyhj5p2wcjy"),
+            output(20.0, "bcrt1q6z3mxr5ljsqyp3t4u3rn0d6ah0kswhv0fune8a"),
+        ];
+        let miner = Ownership {
+            trader: false,
+            miner: true,
+        };
+        let trader = Ownership {
+            trader: true,
+            miner: false,
+        };
+        let roles = assign_output_roles(&vout, &[miner, trader], Amount::from_int_btc(20));
+        assert_eq!(
+            roles,
+            OutputRoles {
+                payment: Some(1),
+                change: Some(0),
+            }
+        );
+
+        // Even identical addresses are told apart by which wallet owns each output
+        let same = "bcrt1q6z3mxr5ljsqyp3t4u3rn0d6ah0kswhv0fune8a";
+        let vout = [output(20.0, same), output(29.9999859, same)];
+        let roles = assign_output_roles(&vout, &[trader, miner], Amount::from_int_btc(20));
+        assert_eq!(
+            roles,
+            OutputRoles {
+                payment: Some(0),
+                change: Some(1),
+            }
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use crate::error::AppError;
+use crate::report::{render_report, OutputFormat, TxReport};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+// Dump everything the run touched about its transaction into `dir`, created if missing:
+// raw.hex, decoded.json, report.json, and block.json once confirmed and psbt.txt in PSBT mode
+// when there is one. Returns the names of the files written.
+pub fn write_artifacts(
+    dir: &Path,
+    raw_hex: &str,
+    decoded: &Value,
+    report: &TxReport,
+    block: Option<&Value>,
+    psbt: Option<&str>,
+) -> Result<Vec<&'static str>, AppError> {
+    fs::create_dir_all(dir).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("could not create directory {}: {e}", dir.display()),
+        )
+    })?;
+    let pretty = |value: &Value| format!("{value:#}\n");
+    let mut files = vec![
+        ("raw.hex", format!("{raw_hex}\n")),
+        ("decoded.json", pretty(decoded)),
+        (
+            "report.json",
+            render_report(report, OutputFormat::Json)? + "\n",
+        ),
+    ];
+    if let Some(block) = block {
+        files.push(("block.json", pretty(block)));
+    }
+    if let Some(psbt) = psbt {
+        files.push(("psbt.txt", format!("{psbt}\n")));
+    }
+    let mut written = Vec::new();
+    for (name, contents) in files {
+        let path = dir.join(name);
+        fs::write(&path, contents).map_err(|e| {
+            std::io::Error::new(e.kind(), format!("could not write {}: {e}", path.display()))
+        })?;
+        written.push(name);
+    }
+    Ok(written)
+}
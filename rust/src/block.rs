@@ -0,0 +1,112 @@
+use crate::analysis::utxo_impact;
+use crate::error::AppError;
+use bitcoincore_rpc::bitcoin::block::Header;
+use bitcoincore_rpc::bitcoin::consensus::deserialize;
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+// Header-level details of the block that confirmed the transaction
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockReport {
+    pub hash: String,
+    pub height: u64,
+    pub merkleroot: String,
+    #[serde(rename = "nTx")]
+    pub n_tx: u64,
+    pub nonce: u32,
+    pub bits: String,
+    // Txids in block order, the coinbase first
+    pub tx: Vec<String>,
+}
+
+impl BlockReport {
+    // Position of `txid` in the block (0 is the coinbase)
+    pub fn tx_index(&self, txid: &str) -> Option<usize> {
+        self.tx.iter().position(|t| t == txid)
+    }
+}
+
+pub fn block_report(rpc: &Client, blockhash: &str) -> Result<BlockReport, AppError> {
+    // Verbosity 1 gives the header fields plus txids, which is all we need here
+    Ok(rpc.call::<BlockReport>("getblock", &[json!(blockhash), json!(1)])?)
+}
+
+// The confirming block's serialized 80-byte header as hex
+pub fn block_header_hex(rpc: &Client, blockhash: &str) -> Result<String, AppError> {
+    Ok(rpc.call::<String>("getblockheader", &[json!(blockhash), json!(false)])?)
+}
+
+// Decode a hex block header into its six fields
+pub fn parse_block_header(header_hex: &str) -> Result<Header, AppError> {
+    let bytes = Vec::<u8>::from_hex(header_hex)
+        .map_err(|e| AppError::Check(format!("block header hex is malformed: {e}")))?;
+    deserialize(&bytes).map_err(|e| AppError::Check(format!("block header does not decode: {e}")))
+}
+
+#[derive(Deserialize)]
+struct ChainTip {
+    height: u64,
+    hash: String,
+    branchlen: u64,
+    status: String,
+}
+
+// Which chain is the block on, as `getchaintips` sees it? Returns "active" when it's part of the
+// best chain, otherwise the status of the side branch containing it (e.g. "valid-fork").
+pub fn chain_status(rpc: &Client, blockhash: &str, height: u64) -> Result<String, AppError> {
+    let tips = rpc.call::<Vec<ChainTip>>("getchaintips", &[])?;
+    let on_active = tips
+        .iter()
+        .any(|tip| tip.status == "active" && tip.height >= height)
+        && rpc.call::<String>("getblockhash", &[json!(height)])? == blockhash;
+    if on_active {
+        return Ok("active".to_string());
+    }
+
+    // A side branch covers heights (tip.height - branchlen, tip.height]; walk back from each
+    // candidate tip to see whether our block is on it
+    for tip in tips.iter().filter(|t| t.status != "active") {
+        if tip.height < height || tip.height - tip.branchlen >= height {
+            continue;
+        }
+        let mut hash = tip.hash.clone();
+        for _ in height..tip.height {
+            let header = rpc.call::<serde_json::Value>("getblockheader", &[json!(hash)])?;
+            hash = header["previousblockhash"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+        }
+        if hash == blockhash {
+            return Ok(tip.status.clone());
+        }
+    }
+    Ok("unknown".to_string())
+}
+
+// Number of outputs in the node's UTXO set. `gettxoutsetinfo` walks the whole set (unless the
+// node keeps coinstatsindex), so this is slow on a large chain.
+pub fn utxo_set_size(rpc: &Client) -> Result<u64, AppError> {
+    let info = rpc.call::<Value>("gettxoutsetinfo", &[])?;
+    info["txouts"]
+        .as_u64()
+        .ok_or_else(|| AppError::Check("gettxoutsetinfo returned no txouts".to_string()))
+}
+
+// Net change in UTXO count the given blocks should cause, from the transactions they contain
+pub fn blocks_utxo_delta(rpc: &Client, hashes: &[String]) -> Result<i64, AppError> {
+    let mut delta = 0;
+    for hash in hashes {
+        let block = rpc.call::<Value>("getblock", &[json!(hash), json!(2)])?;
+        for tx in block["tx"].as_array().into_iter().flatten() {
+            let empty = Vec::new();
+            let vin = tx["vin"].as_array().unwrap_or(&empty);
+            let vout = tx["vout"].as_array().unwrap_or(&empty);
+            let (spent, created) = utxo_impact(vin, vout);
+            delta += created as i64 - spent as i64;
+        }
+    }
+    Ok(delta)
+}
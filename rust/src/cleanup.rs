@@ -0,0 +1,99 @@
+use crate::error::AppError;
+use crate::wallet::list_wallets;
+use bitcoincore_rpc::bitcoin::Network;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde_json::json;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+// What `--cleanup` should tear down beyond unloading the wallets
+pub struct CleanupOptions<'a> {
+    // Remove the wallet directories from the node's datadir after unloading
+    pub delete: bool,
+    // The node's datadir, as seen from this machine; required for `delete`
+    pub datadir: Option<&'a Path>,
+    // Invalidate every block above genesis
+    pub reset_chain: bool,
+    // Skip the confirmation prompt on non-regtest networks
+    pub assume_yes: bool,
+}
+
+// Unload the wallets this program creates and, if asked, delete their files and roll the chain
+// back to genesis so the next run starts from a clean slate. Wallets that aren't loaded are
+// skipped. Anything destructive on a network other than regtest needs an explicit "yes".
+pub fn cleanup(
+    rpc: &Client,
+    wallets: &[&str],
+    network: Network,
+    options: &CleanupOptions,
+) -> Result<(), AppError> {
+    if options.delete && options.datadir.is_none() {
+        return Err(AppError::Usage(
+            "--delete needs --datadir to find the wallet files".into(),
+        ));
+    }
+    if (options.delete || options.reset_chain) && network != Network::Regtest && !options.assume_yes
+    {
+        confirm(&format!(
+            "This will destroy wallet/chain state on {network}."
+        ))?;
+    }
+
+    let loaded = list_wallets(rpc)?;
+    for wallet in wallets {
+        if loaded.iter().any(|w| w == wallet) {
+            rpc.call::<serde_json::Value>("unloadwallet", &[json!(wallet)])?;
+            println!("Unloaded wallet {wallet}");
+        }
+        if let (true, Some(datadir)) = (options.delete, options.datadir) {
+            let dir = wallet_dir(datadir, network, wallet)?;
+            if dir.exists() {
+                fs::remove_dir_all(&dir).map_err(|e| {
+                    io::Error::new(e.kind(), format!("removing {}: {e}", dir.display()))
+                })?;
+                println!("Deleted {}", dir.display());
+            }
+        }
+    }
+
+    // Invalidating the block at height 1 disconnects it and everything built on top of it
+    if options.reset_chain {
+        let height = rpc.get_block_count()?;
+        if height > 0 {
+            let first = rpc.get_block_hash(1)?;
+            rpc.call::<serde_json::Value>("invalidateblock", &[json!(first.to_string())])?;
+            println!("Invalidated {height} blocks back to genesis");
+        }
+    }
+    Ok(())
+}
+
+// Descriptor wallets live in <datadir>/<network subdir>/wallets/<name>, mainnet's directly in
+// <datadir>/wallets. A network we don't know the layout of is refused rather than guessed at,
+// since a wrong guess could point the deletion at mainnet's wallets.
+fn wallet_dir(datadir: &Path, network: Network, wallet: &str) -> Result<PathBuf, AppError> {
+    let subdir = match network {
+        Network::Bitcoin => "",
+        Network::Testnet => "testnet3",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+        other => {
+            return Err(AppError::Usage(format!(
+                "don't know where {other} keeps its wallets; delete them by hand"
+            )))
+        }
+    };
+    Ok(datadir.join(subdir).join("wallets").join(wallet))
+}
+
+fn confirm(warning: &str) -> Result<(), AppError> {
+    print!("{warning} Type 'yes' to continue: ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if answer.trim() != "yes" {
+        return Err(AppError::Usage("cleanup aborted".into()));
+    }
+    Ok(())
+}
@@ -0,0 +1,334 @@
+use crate::config::Config;
+use crate::error::AppError;
+use crate::report::OutputFormat;
+use crate::wait::WaitConfig;
+use crate::SendMethod;
+use bitcoincore_rpc::bitcoin::{Amount, Network};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Command-line options. Every flag is optional: with no arguments the program runs the
+// standard capstone flow. Settings are layered: defaults, then CAPSTONE_* environment
+// variables, then the --config file, then the flags themselves.
+#[derive(Debug)]
+pub struct Args {
+    // Base URL of the node's JSON-RPC server
+    pub rpc_url: String,
+    // RPC credentials
+    pub rpc_user: String,
+    pub rpc_pass: String,
+    // host:port of a SOCKS5 proxy (e.g. Tor) to reach the node through
+    pub proxy: Option<String>,
+    // Network the node runs on; addresses are validated against it
+    pub network: Network,
+    // Only check that the node and output path are usable, then exit
+    pub check_env: bool,
+    // Unload the generated wallets instead of running the flow
+    pub cleanup: bool,
+    // With --cleanup, also delete the wallet files under --datadir
+    pub delete: bool,
+    // The node's data directory, for --delete
+    pub datadir: Option<PathBuf>,
+    // With --cleanup, invalidate every block back to genesis
+    pub reset_chain: bool,
+    // Don't ask before destructive cleanup on non-regtest networks
+    pub yes: bool,
+    // Build the report from the raw transaction hex in this file instead of sending one
+    pub from_raw: Option<PathBuf>,
+    // Verify loaded wallets carry this node's context marker
+    pub strict_wallet: bool,
+    // Hex seed the wallets' keys are derived from, for reproducible addresses
+    pub seed: Option<String>,
+    // Descriptor to import into the Miner wallet (the checksum is optional)
+    pub import_descriptor: Option<String>,
+    // Dump the wallets' descriptors and chain height here after setup
+    pub export_scenario: Option<PathBuf>,
+    // Re-import wallet descriptors from a previously exported scenario
+    pub import_scenario: Option<PathBuf>,
+    // Include private keys in --export-scenario (they are redacted otherwise)
+    pub unsafe_export_keys: bool,
+    // Polling interval and timeout shared by every wait loop (--poll-interval-ms and
+    // --poll-timeout-secs; the units are in the flag names since they differ)
+    pub wait: WaitConfig,
+    // Print both wallets' transactions as a merged timeline at the end
+    pub history: bool,
+    // Write Prometheus text-format metrics about the run here
+    pub metrics_file: Option<PathBuf>,
+    // Write a JSON summary of the run's outcome here, even if it fails
+    pub status_file: Option<PathBuf>,
+    // Where the ten-line report is written
+    pub out: PathBuf,
+    // Write the raw and decoded transaction, report, block and PSBT into this directory
+    pub artifacts_dir: Option<PathBuf>,
+    // Read the written text report back and check it parses to the same values
+    pub verify_roundtrip: bool,
+    // Layout of the report file
+    pub format: OutputFormat,
+    // End the report's last line with a newline (disable with --no-trailing-newline)
+    pub trailing_newline: bool,
+    // Abort if the chain is already higher than this before the run mines anything
+    pub max_height: Option<u64>,
+    // Confirmations a coinbase output needs before it can be spent on this chain
+    pub maturity: u32,
+    // Block subsidy to expect instead of the standard 50 BTC halving schedule
+    pub expected_subsidy: Option<Amount>,
+    // Mine the initial blocks to this (non-ranged) descriptor instead of a wallet address
+    pub mine_to_descriptor: Option<String>,
+    // Share the initial blocks round-robin between the Miner and these addresses
+    pub mine_to_many: Vec<String>,
+    // Mine one block to child --xpub-index of this xpub, watched from a keyless wallet
+    pub mine_to_xpub: Option<String>,
+    pub xpub_index: u32,
+    // Nonces generatetoaddress may try per call before giving up (Core's default if unset)
+    pub max_tries: Option<u64>,
+    // Mine the initial blocks in chunks and print progress
+    pub show_progress: bool,
+    // After the run, fund a 2-of-3 multisig and spend from it with two signatures
+    pub multisig: bool,
+    // After the run, keep sending and confirming every this many seconds until Ctrl-C
+    pub loop_interval: Option<u64>,
+    // Where --loop appends one JSON line per iteration
+    pub loop_log: PathBuf,
+    // Mine until the send has this many confirmations before reporting
+    pub confirmations: u64,
+    // Send but don't mine the confirming block; report from the mempool
+    pub no_confirm: bool,
+    // Have the Trader spend the payment on while it's still unconfirmed
+    pub chain: bool,
+    // Seconds to watch the mempool for a double-spend of the send's inputs
+    pub watch_double_spend: Option<u64>,
+    // Check gettxoutsetinfo's UTXO count moved by what the confirming blocks did (slow)
+    pub verify_utxo_set: bool,
+    // Extra blocks to mine after the confirming block, to deepen its confirmations
+    pub mine_empty: u64,
+    // Write the blocks mined by this run and their coinbase addresses here
+    pub blocks_out: Option<PathBuf>,
+    // How much the Miner sends to the Trader
+    pub amount: Amount,
+    // Print the decoded transaction as a tree of inputs and outputs
+    pub show_tree: bool,
+    // Print merkle root, transaction count, nonce and bits of the confirming block
+    pub block_details: bool,
+    // Fee rate (sat/vB) to pay when the node has no fee estimate, e.g. on regtest
+    pub fallback_fee_rate: Option<f64>,
+    // Send the Miner's change here instead of a fresh change address
+    pub change_address: Option<String>,
+    // Write the confirming block's raw header (hex) here and log its fields
+    pub header_out: Option<PathBuf>,
+    // Miner UTXOs (txid:vout) to lock so coin selection leaves them alone; repeatable
+    pub lock_utxos: Vec<(String, u32)>,
+    // RPC that makes the plain payment: sendtoaddress (the default) or send
+    pub send_method: SendMethod,
+    // Ask the wallet to signal BIP125 replace-by-fee on the send
+    pub replaceable: bool,
+    // After confirming, disconnect and reconnect this many tip blocks
+    pub reorg: Option<u64>,
+    // Build and test the send with testmempoolaccept, but don't broadcast it
+    pub dry_run: bool,
+    // Fund the send from both the Miner and a third wallet via createrawtransaction
+    pub multi_wallet_send: bool,
+    // Build the send with walletcreatefundedpsbt / walletprocesspsbt / finalizepsbt
+    pub psbt: bool,
+    // Where to write each intermediate PSBT when running in PSBT mode
+    pub psbt_dir: Option<PathBuf>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            rpc_url: crate::RPC_URL.to_string(),
+            rpc_user: crate::RPC_USER.to_string(),
+            rpc_pass: crate::RPC_PASS.to_string(),
+            proxy: None,
+            network: Network::Regtest,
+            check_env: false,
+            cleanup: false,
+            delete: false,
+            datadir: None,
+            reset_chain: false,
+            yes: false,
+            from_raw: None,
+            strict_wallet: false,
+            seed: None,
+            import_descriptor: None,
+            export_scenario: None,
+            import_scenario: None,
+            unsafe_export_keys: false,
+            wait: WaitConfig::default(),
+            history: false,
+            metrics_file: None,
+            status_file: None,
+            out: PathBuf::from("../out.txt"),
+            artifacts_dir: None,
+            verify_roundtrip: false,
+            format: OutputFormat::Text,
+            trailing_newline: true,
+            max_height: None,
+            maturity: crate::mining::COINBASE_MATURITY,
+            expected_subsidy: None,
+            mine_to_descriptor: None,
+            mine_to_many: Vec::new(),
+            mine_to_xpub: None,
+            xpub_index: 0,
+            max_tries: None,
+            show_progress: false,
+            multisig: false,
+            loop_interval: None,
+            loop_log: PathBuf::from("../loop.jsonl"),
+            confirmations: 1,
+            no_confirm: false,
+            chain: false,
+            watch_double_spend: None,
+            verify_utxo_set: false,
+            mine_empty: 0,
+            blocks_out: None,
+            amount: Amount::from_int_btc(20),
+            show_tree: false,
+            block_details: false,
+            fallback_fee_rate: None,
+            change_address: None,
+            header_out: None,
+            lock_utxos: Vec::new(),
+            send_method: SendMethod::SendToAddress,
+            replaceable: false,
+            reorg: None,
+            dry_run: false,
+            multi_wallet_send: false,
+            psbt: false,
+            psbt_dir: None,
+        }
+    }
+}
+
+impl Args {
+    // Just the --status-file path, picked out of the raw arguments ahead of the full parse so
+    // that a bad command line still gets its status file
+    pub fn status_file_from(args: &[String]) -> Option<PathBuf> {
+        let i = args.iter().position(|a| a == "--status-file")?;
+        args.get(i + 1).map(PathBuf::from)
+    }
+
+    pub fn parse_from<I: IntoIterator<Item = String>>(args: I) -> Result<Self, AppError> {
+        let args: Vec<String> = args.into_iter().collect();
+        let mut parsed = Args::default();
+        Config::from_env().apply(&mut parsed)?;
+        // The config file has to be applied before any flag, wherever --config appears
+        if let Some(i) = args.iter().position(|a| a == "--config") {
+            let path: PathBuf = value(&mut args[i + 1..].iter().cloned(), "--config")?;
+            Config::load(&path)?.apply(&mut parsed)?;
+        }
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    args.next();
+                }
+                "--rpc-url" => parsed.rpc_url = value(&mut args, &arg)?,
+                "--rpc-user" => parsed.rpc_user = value(&mut args, &arg)?,
+                "--rpc-pass" => parsed.rpc_pass = value(&mut args, &arg)?,
+                "--proxy" => parsed.proxy = Some(value(&mut args, &arg)?),
+                "--network" => parsed.network = value(&mut args, &arg)?,
+                "--check-env" => parsed.check_env = true,
+                "--cleanup" => parsed.cleanup = true,
+                "--delete" => parsed.delete = true,
+                "--datadir" => parsed.datadir = Some(value(&mut args, &arg)?),
+                "--reset-chain" => parsed.reset_chain = true,
+                "--yes" => parsed.yes = true,
+                "--from-raw" => parsed.from_raw = Some(value(&mut args, &arg)?),
+                "--strict-wallet" => parsed.strict_wallet = true,
+                "--seed" => parsed.seed = Some(value(&mut args, &arg)?),
+                "--import-descriptor" => parsed.import_descriptor = Some(value(&mut args, &arg)?),
+                "--export-scenario" => parsed.export_scenario = Some(value(&mut args, &arg)?),
+                "--import-scenario" => parsed.import_scenario = Some(value(&mut args, &arg)?),
+                "--unsafe-export-keys" => parsed.unsafe_export_keys = true,
+                "--poll-interval-ms" => {
+                    parsed.wait.interval = Duration::from_millis(value(&mut args, &arg)?)
+                }
+                "--poll-timeout-secs" => {
+                    parsed.wait.timeout = Duration::from_secs(value(&mut args, &arg)?)
+                }
+                "--history" => parsed.history = true,
+                "--metrics-file" => parsed.metrics_file = Some(value(&mut args, &arg)?),
+                "--status-file" => parsed.status_file = Some(value(&mut args, &arg)?),
+                "--out" => parsed.out = value(&mut args, &arg)?,
+                "--artifacts-dir" => parsed.artifacts_dir = Some(value(&mut args, &arg)?),
+                "--verify-roundtrip" => parsed.verify_roundtrip = true,
+                "--format" => parsed.format = value(&mut args, &arg)?,
+                "--no-trailing-newline" => parsed.trailing_newline = false,
+                "--max-height" => parsed.max_height = Some(value(&mut args, &arg)?),
+                "--maturity" => parsed.maturity = value(&mut args, &arg)?,
+                "--expected-subsidy" => parsed.expected_subsidy = Some(btc_value(&mut args, &arg)?),
+                "--mine-to-descriptor" => parsed.mine_to_descriptor = Some(value(&mut args, &arg)?),
+                "--mine-to-many" => {
+                    let list: String = value(&mut args, &arg)?;
+                    parsed.mine_to_many = list.split(',').map(|a| a.trim().to_string()).collect();
+                }
+                "--mine-to-xpub" => parsed.mine_to_xpub = Some(value(&mut args, &arg)?),
+                "--xpub-index" => parsed.xpub_index = value(&mut args, &arg)?,
+                "--max-tries" => parsed.max_tries = Some(value(&mut args, &arg)?),
+                "--show-progress" => parsed.show_progress = true,
+                "--multisig" => parsed.multisig = true,
+                "--loop" => parsed.loop_interval = Some(value(&mut args, &arg)?),
+                "--loop-log" => parsed.loop_log = value(&mut args, &arg)?,
+                "--confirmations" => parsed.confirmations = value(&mut args, &arg)?,
+                "--no-confirm" => parsed.no_confirm = true,
+                "--chain" => parsed.chain = true,
+                "--watch-double-spend" => parsed.watch_double_spend = Some(value(&mut args, &arg)?),
+                "--verify-utxo-set" => parsed.verify_utxo_set = true,
+                "--mine-empty" => parsed.mine_empty = value(&mut args, &arg)?,
+                "--blocks-out" => parsed.blocks_out = Some(value(&mut args, &arg)?),
+                "--amount" => parsed.amount = btc_value(&mut args, &arg)?,
+                "--show-tree" => parsed.show_tree = true,
+                "--block-details" => parsed.block_details = true,
+                "--fallback-fee-rate" => parsed.fallback_fee_rate = Some(value(&mut args, &arg)?),
+                "--change-address" => parsed.change_address = Some(value(&mut args, &arg)?),
+                "--header-out" => parsed.header_out = Some(value(&mut args, &arg)?),
+                "--lock-utxo" => parsed.lock_utxos.push(outpoint_value(&mut args, &arg)?),
+                "--send-method" => parsed.send_method = value(&mut args, &arg)?,
+                "--replaceable" => parsed.replaceable = true,
+                "--reorg" => parsed.reorg = Some(value(&mut args, &arg)?),
+                "--dry-run" => parsed.dry_run = true,
+                "--multi-wallet-send" => parsed.multi_wallet_send = true,
+                "--psbt" => parsed.psbt = true,
+                "--psbt-dir" => parsed.psbt_dir = Some(value(&mut args, &arg)?),
+                other => return Err(AppError::Usage(format!("unknown argument: {other}"))),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+// Take the value following `flag` and parse it into the expected type
+fn value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, AppError>
+where
+    T::Err: std::fmt::Display,
+{
+    let raw = args
+        .next()
+        .ok_or_else(|| AppError::Usage(format!("{flag} requires a value")))?;
+    raw.parse()
+        .map_err(|e| AppError::Usage(format!("invalid value for {flag} ({raw}): {e}")))
+}
+
+// Take the value following `flag` as a decimal BTC amount, e.g. "20" or "0.0001"
+fn btc_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<Amount, AppError> {
+    let btc: f64 = value(args, flag)?;
+    Amount::from_btc(btc).map_err(|e| AppError::Usage(format!("invalid value for {flag}: {e}")))
+}
+
+// Take the value following `flag` as an outpoint, "txid:vout"
+fn outpoint_value(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+) -> Result<(String, u32), AppError> {
+    let raw: String = value(args, flag)?;
+    let invalid = || {
+        AppError::Usage(format!(
+            "invalid value for {flag} ({raw}): expected txid:vout"
+        ))
+    };
+    let (txid, vout) = raw.split_once(':').ok_or_else(invalid)?;
+    let vout = vout.parse().map_err(|_| invalid())?;
+    Ok((txid.to_string(), vout))
+}
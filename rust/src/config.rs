@@ -0,0 +1,125 @@
+use crate::cli::Args;
+use crate::error::AppError;
+use crate::report::OutputFormat;
+use bitcoincore_rpc::bitcoin::{Amount, Network};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Settings a --config TOML file may carry. Every key is optional; whatever is set overrides
+// the environment and defaults, and is itself overridden by command-line flags.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub rpc_url: Option<String>,
+    pub rpc_user: Option<String>,
+    pub rpc_pass: Option<String>,
+    pub network: Option<String>,
+    // In BTC, e.g. 20 or 0.5
+    pub amount: Option<f64>,
+    pub maturity: Option<u32>,
+    pub confirmations: Option<u64>,
+    pub mine_empty: Option<u64>,
+    pub out: Option<PathBuf>,
+    pub format: Option<String>,
+}
+
+// Keys of Config, to spot typos in a config file
+const KNOWN_KEYS: &[&str] = &[
+    "rpc_url",
+    "rpc_user",
+    "rpc_pass",
+    "network",
+    "amount",
+    "maturity",
+    "confirmations",
+    "mine_empty",
+    "out",
+    "format",
+];
+
+// Environment variables read before the config file, for settings that shouldn't have to live
+// in a file or on the command line (credentials in particular)
+const ENV_VARS: &[(&str, &str)] = &[
+    ("CAPSTONE_RPC_URL", "rpc_url"),
+    ("CAPSTONE_RPC_USER", "rpc_user"),
+    ("CAPSTONE_RPC_PASS", "rpc_pass"),
+    ("CAPSTONE_NETWORK", "network"),
+];
+
+impl Config {
+    // Read a TOML config file. Unknown keys are warned about rather than rejected, so a file
+    // written for a newer version still loads.
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            std::io::Error::new(e.kind(), format!("could not read {}: {e}", path.display()))
+        })?;
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e| AppError::Usage(format!("invalid config {}: {e}", path.display())))?;
+        for key in table.keys().filter(|k| !KNOWN_KEYS.contains(&k.as_str())) {
+            eprintln!("Warning: unknown key {key} in {}", path.display());
+        }
+        table
+            .try_into()
+            .map_err(|e| AppError::Usage(format!("invalid config {}: {e}", path.display())))
+    }
+
+    // The CAPSTONE_* environment variables that are set
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            ENV_VARS
+                .iter()
+                .find(|(_, key)| *key == name)
+                .and_then(|(var, _)| std::env::var(var).ok())
+        };
+        Config {
+            rpc_url: var("rpc_url"),
+            rpc_user: var("rpc_user"),
+            rpc_pass: var("rpc_pass"),
+            network: var("network"),
+            ..Config::default()
+        }
+    }
+
+    // Overwrite the settings this config specifies
+    pub fn apply(&self, args: &mut Args) -> Result<(), AppError> {
+        let invalid = |key: &str, e: &dyn std::fmt::Display| {
+            AppError::Usage(format!("invalid value for {key}: {e}"))
+        };
+        if let Some(url) = &self.rpc_url {
+            args.rpc_url = url.clone();
+        }
+        if let Some(user) = &self.rpc_user {
+            args.rpc_user = user.clone();
+        }
+        if let Some(pass) = &self.rpc_pass {
+            args.rpc_pass = pass.clone();
+        }
+        if let Some(network) = &self.network {
+            args.network = network
+                .parse::<Network>()
+                .map_err(|e| invalid("network", &e))?;
+        }
+        if let Some(amount) = self.amount {
+            args.amount = Amount::from_btc(amount).map_err(|e| invalid("amount", &e))?;
+        }
+        if let Some(maturity) = self.maturity {
+            args.maturity = maturity;
+        }
+        if let Some(confirmations) = self.confirmations {
+            args.confirmations = confirmations;
+        }
+        if let Some(mine_empty) = self.mine_empty {
+            args.mine_empty = mine_empty;
+        }
+        if let Some(out) = &self.out {
+            args.out = out.clone();
+        }
+        if let Some(format) = &self.format {
+            args.format = format
+                .parse::<OutputFormat>()
+                .map_err(|e| invalid("format", &e))?;
+        }
+        Ok(())
+    }
+}
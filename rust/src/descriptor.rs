@@ -0,0 +1,127 @@
+use crate::error::AppError;
+use bitcoincore_rpc::bitcoin::bip32::{ChildNumber, Xpriv, Xpub};
+use bitcoincore_rpc::bitcoin::secp256k1::Secp256k1;
+use bitcoincore_rpc::bitcoin::{Address, Network};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Deserialize)]
+struct DescriptorInfo {
+    descriptor: String,
+    checksum: String,
+    isrange: bool,
+}
+
+#[derive(Deserialize)]
+struct RpcMessage {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ImportResult {
+    success: bool,
+    error: Option<RpcMessage>,
+}
+
+// Return `descriptor` with its checksum appended, as computed by `getdescriptorinfo`, so users
+// can pass descriptors without one. A descriptor that already has a checksum is checked by the
+// node; the private keys in the input are kept (the canonical form would strip them).
+pub fn add_descriptor_checksum(rpc: &Client, descriptor: &str) -> Result<String, AppError> {
+    let info = rpc.call::<DescriptorInfo>("getdescriptorinfo", &[json!(descriptor)])?;
+    println!("Canonical descriptor: {}", info.descriptor);
+    let body = descriptor.split('#').next().unwrap_or(descriptor);
+    Ok(format!("{body}#{}", info.checksum))
+}
+
+// Validate a descriptor that blocks will be mined to and return it with its checksum.
+// `generatetodescriptor` needs a single script, so ranged descriptors (ending in /*) are
+// refused here with a hint rather than by the node.
+pub fn mining_descriptor(rpc: &Client, descriptor: &str) -> Result<String, AppError> {
+    let info = rpc.call::<DescriptorInfo>("getdescriptorinfo", &[json!(descriptor)])?;
+    if info.isrange {
+        return Err(AppError::Descriptor(format!(
+            "{descriptor} is ranged; pick one child, e.g. .../0/5 instead of .../0/*"
+        )));
+    }
+    add_descriptor_checksum(rpc, descriptor)
+}
+
+// Import a descriptor into the wallet, adding its checksum first if it's missing
+pub fn import_descriptor(rpc: &Client, descriptor: &str) -> Result<(), AppError> {
+    let descriptor = add_descriptor_checksum(rpc, descriptor)?;
+    import_descriptors(rpc, vec![json!({ "desc": descriptor, "timestamp": "now" })])?;
+    println!("Imported descriptor {descriptor}");
+    Ok(())
+}
+
+// Send a batch of `importdescriptors` requests, failing on the first one the wallet rejects
+pub fn import_descriptors(rpc: &Client, requests: Vec<Value>) -> Result<(), AppError> {
+    let results = rpc.call::<Vec<ImportResult>>("importdescriptors", &[json!(requests)])?;
+    for (request, result) in requests.iter().zip(results) {
+        if !result.success {
+            let reason = result
+                .error
+                .map(|e| e.message)
+                .unwrap_or_else(|| "unknown error".to_string());
+            return Err(AppError::Descriptor(format!(
+                "import of {} failed: {reason}",
+                request["desc"]
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Make a wallet's addresses reproducible: derive a BIP32 master key from `seed` and import
+// BIP84 receive and change descriptors for `account` as the wallet's active descriptors, so
+// getnewaddress hands out the same sequence on every fresh wallet. Each wallet should get its
+// own account number. This takes over from the wallet's own keys, so only use it on
+// disposable regtest wallets.
+pub fn import_seed(
+    rpc: &Client,
+    seed: &[u8],
+    account: u32,
+    network: Network,
+) -> Result<(), AppError> {
+    let master = Xpriv::new_master(network, seed)
+        .map_err(|e| AppError::Descriptor(format!("seed can't be used as a BIP32 seed: {e}")))?;
+    let coin_type = if network == Network::Bitcoin { 0 } else { 1 };
+    let mut requests = Vec::new();
+    for (chain, internal) in [(0, false), (1, true)] {
+        let descriptor = format!("wpkh({master}/84h/{coin_type}h/{account}h/{chain}/*)");
+        requests.push(json!({
+            "desc": add_descriptor_checksum(rpc, &descriptor)?,
+            "timestamp": "now",
+            "active": true,
+            "internal": internal,
+        }));
+    }
+    import_descriptors(rpc, requests)?;
+    println!("Imported seed-derived descriptors for account {account}");
+    Ok(())
+}
+
+// The P2WPKH address of the non-hardened child `index` of `xpub`. Only public derivation is
+// possible without the private key, which is the point: whoever holds the xpub can watch the
+// address but not spend from it.
+pub fn xpub_child_address(xpub: &str, index: u32, network: Network) -> Result<Address, AppError> {
+    let xpub: Xpub = xpub
+        .parse()
+        .map_err(|e| AppError::Descriptor(format!("invalid xpub {xpub}: {e}")))?;
+    let child = ChildNumber::from_normal_idx(index)
+        .map_err(|e| AppError::Descriptor(format!("invalid child index {index}: {e}")))?;
+    let derived = xpub
+        .derive_pub(&Secp256k1::verification_only(), &[child])
+        .map_err(|e| AppError::Descriptor(format!("could not derive child {index}: {e}")))?;
+    Address::p2wpkh(&derived.to_pub(), network)
+        .map_err(|e| AppError::Address(format!("no P2WPKH address for child {index}: {e}")))
+}
+
+// Watch `address` from a wallet without private keys (an `addr()` descriptor)
+pub fn import_watch_only(rpc: &Client, address: &Address) -> Result<(), AppError> {
+    let descriptor = add_descriptor_checksum(rpc, &format!("addr({address})"))?;
+    import_descriptors(rpc, vec![json!({ "desc": descriptor, "timestamp": "now" })])?;
+    println!("Watching {address}");
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use bitcoincore_rpc::bitcoin::amount::ParseAmountError;
+use bitcoincore_rpc::Error as RpcError;
+use std::fmt;
+
+// Errors surfaced by the program. Wraps the RPC and IO failures we can hit talking to the
+// node and writing the report, plus the conditions the program detects on its own.
+#[derive(Debug)]
+pub enum AppError {
+    Rpc(RpcError),
+    Io(std::io::Error),
+    // A BTC value from the node could not be represented as an Amount
+    Amount(ParseAmountError),
+    // A sanity check on amounts or transaction structure failed
+    Check(String),
+    // An address from the node or the user is malformed or for the wrong network
+    Address(String),
+    // A wait loop gave up before the node reached the expected state
+    Timeout(String),
+    // Bad command-line arguments
+    Usage(String),
+    // A descriptor could not be imported
+    Descriptor(String),
+    // A scenario file could not be written or read back
+    Scenario(String),
+    // A PSBT step left the transaction incomplete
+    Psbt(String),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Rpc(e) => write!(f, "RPC error: {e}"),
+            AppError::Io(e) => write!(f, "IO error: {e}"),
+            AppError::Amount(e) => write!(f, "Invalid amount: {e}"),
+            AppError::Check(msg) => write!(f, "Check failed: {msg}"),
+            AppError::Address(msg) => write!(f, "Invalid address: {msg}"),
+            AppError::Timeout(msg) => write!(f, "Timed out: {msg}"),
+            AppError::Usage(msg) => write!(f, "Usage error: {msg}"),
+            AppError::Descriptor(msg) => write!(f, "Descriptor error: {msg}"),
+            AppError::Scenario(msg) => write!(f, "Scenario error: {msg}"),
+            AppError::Psbt(msg) => write!(f, "PSBT error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Rpc(e) => Some(e),
+            AppError::Io(e) => Some(e),
+            AppError::Amount(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<RpcError> for AppError {
+    fn from(e: RpcError) -> Self {
+        AppError::Rpc(e)
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Io(e)
+    }
+}
+
+impl From<ParseAmountError> for AppError {
+    fn from(e: ParseAmountError) -> Self {
+        AppError::Amount(e)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::error::AppError;
+use crate::rawtx::estimate_vsize;
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::json;
+
+// Confirmation target (in blocks) used when asking the node for fee estimates
+pub const FEE_CONF_TARGET: u16 = 6;
+
+// Lowest rate the node relays at (-minrelaytxfee), assumed when the wallet picks the rate
+const MIN_RELAY_FEE_RATE_SAT_VB: f64 = 1.0;
+
+#[derive(Deserialize)]
+struct SmartFee {
+    // BTC/kvB; absent when the node has no data to estimate from
+    feerate: Option<f64>,
+}
+
+// Ask `estimatesmartfee` for a rate in BTC/kvB using the given estimate mode
+pub fn estimate_fee_rate(
+    rpc: &Client,
+    conf_target: u16,
+    mode: &str,
+) -> Result<Option<f64>, AppError> {
+    let estimate = rpc.call::<SmartFee>("estimatesmartfee", &[json!(conf_target), json!(mode)])?;
+    Ok(estimate.feerate)
+}
+
+// Compare the two estimation strategies: "economical" reacts to recent blocks, "conservative"
+// looks further back and so tends to be higher. Both are logged side by side; on regtest there
+// is usually no fee history, so both come back as None.
+pub fn compare_fee_modes(
+    rpc: &Client,
+    conf_target: u16,
+) -> Result<(Option<f64>, Option<f64>), AppError> {
+    let economical = estimate_fee_rate(rpc, conf_target, "economical")?;
+    let conservative = estimate_fee_rate(rpc, conf_target, "conservative")?;
+    let show = |rate: Option<f64>| match rate {
+        Some(rate) => format!("{rate} BTC/kvB"),
+        None => "no estimate (not enough fee data)".to_string(),
+    };
+    println!(
+        "Fee estimates for {conf_target} blocks: economical {}, conservative {}",
+        show(economical),
+        show(conservative)
+    );
+    Ok((economical, conservative))
+}
+
+// Core parses a `fee_rate` with at most 3 decimals (sat/vB is mapped onto whole sat/kvB) and
+// rejects anything finer as an invalid amount, so rates are rounded to that before sending
+fn round_fee_rate(rate: f64) -> f64 {
+    (rate * 1000.0).round() / 1000.0
+}
+
+// Fee rate in sat/vB to pass to the send, given the node's estimate (BTC/kvB) and the user's
+// --fallback-fee-rate. Without a fallback the wallet is left to its own defaults (None); with
+// one the estimate wins when there is one, and the fallback covers regtest's missing data.
+pub fn choose_fee_rate(estimate: Option<f64>, fallback: Option<f64>) -> Option<f64> {
+    let fallback = fallback?;
+    match estimate {
+        Some(btc_per_kvb) => {
+            // 1 BTC/kvB = 100_000_000 sat / 1000 vB
+            let rate = round_fee_rate(btc_per_kvb * 100_000.0);
+            println!("Using the node's fee estimate of {rate} sat/vB");
+            Some(rate)
+        }
+        None => {
+            let rate = round_fee_rate(fallback);
+            println!("No fee estimate available, using fallback of {rate} sat/vB");
+            Some(rate)
+        }
+    }
+}
+
+// Rough fee for a one-input, two-output send at `fee_rate` sat/vB, or at the minimum relay rate
+// when the wallet is left to pick one. Only good for preflight checks: the wallet's coin
+// selection may need more inputs.
+pub fn estimate_send_fee(fee_rate: Option<f64>) -> Amount {
+    let rate = fee_rate.unwrap_or(MIN_RELAY_FEE_RATE_SAT_VB);
+    Amount::from_sat((rate * estimate_vsize(1, 2) as f64).ceil() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fee_rates_are_rounded_to_three_decimals() {
+        // 0.00012345 BTC/kvB comes out of the multiplication as 12.344999999999999
+        assert_eq!(choose_fee_rate(Some(0.00012345), Some(1.0)), Some(12.345));
+        assert_eq!(choose_fee_rate(None, Some(2.00049)), Some(2.0));
+        assert_eq!(choose_fee_rate(Some(0.0001), None), None);
+    }
+}
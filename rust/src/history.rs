@@ -0,0 +1,59 @@
+use crate::error::AppError;
+use bitcoincore_rpc::bitcoin::SignedAmount;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::json;
+
+// How many of each wallet's most recent transactions to include
+const HISTORY_LIMIT: usize = 1000;
+
+// One `listtransactions` entry, tagged with the wallet it came from
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalletTxEntry {
+    #[serde(skip)]
+    pub wallet: String,
+    pub txid: String,
+    // send, receive, generate (mature coinbase) or immature
+    pub category: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub amount: SignedAmount,
+    pub confirmations: i64,
+    pub time: u64,
+}
+
+// Every transaction the given wallets know about, merged and ordered by time
+pub fn wallet_history(wallets: &[(&str, &Client)]) -> Result<Vec<WalletTxEntry>, AppError> {
+    let mut entries = Vec::new();
+    for (name, rpc) in wallets {
+        let txs = rpc
+            .call::<Vec<WalletTxEntry>>("listtransactions", &[json!("*"), json!(HISTORY_LIMIT)])?;
+        entries.extend(txs.into_iter().map(|mut tx| {
+            tx.wallet = name.to_string();
+            tx
+        }));
+    }
+    // Blocks mined in the same second share a timestamp; keep send before receive within one
+    entries.sort_by(|a, b| {
+        a.time
+            .cmp(&b.time)
+            .then(a.category.cmp(&b.category).reverse())
+    });
+    Ok(entries)
+}
+
+// One line per entry: time, wallet, category, signed amount, confirmations, txid
+pub fn render_timeline(txs: &[WalletTxEntry]) -> String {
+    let mut out = String::new();
+    for tx in txs {
+        out.push_str(&format!(
+            "{:>10}  {:<6}  {:<9}  {:>+14.8} BTC  {:>4} conf  {}\n",
+            tx.time,
+            tx.wallet,
+            tx.category,
+            tx.amount.to_btc(),
+            tx.confirmations,
+            tx.txid
+        ));
+    }
+    out
+}
@@ -1,14 +1,96 @@
-#![allow(unused)]
-use bitcoincore_rpc::bitcoin::Amount;
-use bitcoincore_rpc::bitcoin::{Address, Network};
-use bitcoincore_rpc::jsonrpc;
-use bitcoincore_rpc::Error as RpcError;
-use bitcoincore_rpc::{Auth, Client, RpcApi};
+mod analysis;
+mod artifacts;
+mod block;
+mod cleanup;
+mod cli;
+mod config;
+mod descriptor;
+mod error;
+mod fees;
+mod history;
+mod mempool;
+mod metrics;
+mod mining;
+mod multisig;
+mod preflight;
+mod proxy;
+mod psbt;
+mod rawtx;
+mod report;
+mod rpc;
+mod scenario;
+mod signal;
+mod status;
+mod timing;
+mod traffic;
+mod wait;
+mod wallet;
+
+use analysis::{
+    assign_output_roles, change_ratio, check_dust, coinbase_maturity_height, effective_fee_rate,
+    fee_in_units, is_segwit, net_position, op_return_data, signals_rbf, utxo_impact, value_totals,
+    verify_value_balance, witness_discount_vbytes, Ownership,
+};
+use artifacts::write_artifacts;
+use bitcoincore_rpc::bitcoin::hex::FromHex;
+use bitcoincore_rpc::bitcoin::{Amount, SignedAmount};
+use bitcoincore_rpc::{Client, RpcApi};
+use block::{
+    block_header_hex, block_report, blocks_utxo_delta, chain_status, parse_block_header,
+    utxo_set_size,
+};
+use cleanup::{cleanup, CleanupOptions};
+use cli::Args;
+use descriptor::{
+    import_descriptor, import_seed, import_watch_only, mining_descriptor, xpub_child_address,
+};
+use error::AppError;
+use fees::{choose_fee_rate, compare_fee_modes, estimate_send_fee, FEE_CONF_TARGET};
+use history::{render_timeline, wallet_history};
+use mempool::{fetch_mempool_entry, mempool_snapshot, wait_for_mempool, watch_double_spend};
+use metrics::{write_metrics, Metrics};
+use mining::{
+    check_subsidy, mature_coinbase_count, mine_blocks, mine_round_robin, mine_to_confirmations,
+    mine_to_descriptor, mined_rewards, report_mined_blocks, simulate_reorg, write_mined_blocks,
+};
+use multisig::{multisig_demo, MultisigOptions};
+use preflight::check_env;
+use psbt::psbt_send;
+use rawtx::{
+    build_signed, chain_spend, multi_wallet_send, report_from_raw, size_breakdown, test_accept,
+    verify_txid,
+};
+use report::{
+    parse_report_file, render_tx_tree, text_contract, write_report, OutputFormat, TxReport,
+};
+use rpc::{
+    call_named, explain_reject_reason, explain_send_failure, fetch_txs_batch, node_version,
+    probe_capabilities, NodeConfig, SENDTOADDRESS_PARAMS, SEND_PARAMS,
+};
+use scenario::{export_scenario, import_scenario};
 use serde::Deserialize;
 use serde_json::json;
-use std::fs::File;
-use std::io::Write;
+use status::StatusGuard;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
+use timing::{confirmation_summary, PhaseTimer};
+use traffic::{send_loop, LoopOptions};
+use wait::WaitConfig;
+use wallet::{
+    assert_single_new_tx, assert_trader_utxo, avoid_reuse, check_address_reuse,
+    check_wallet_context, classify_outputs, consumed_utxos, decoded_tx, ensure_keypool,
+    ensure_wallet, ensure_watch_only_wallet, hd_key_path, is_mine, lifetime_fees, list_unspent,
+    list_wallets, new_address, parse_address, preflight_balance, send_with_change, trace_prevout,
+    wallet_tx_count, watch_only_balance, UtxoLock,
+};
+
+// Third wallet funding part of the send in --multi-wallet-send mode
+const FUNDER_WALLET: &str = "Funder";
+// Keyless wallet watching the --mine-to-xpub address
+const WATCH_ONLY_WALLET: &str = "WatchOnly";
+// Third key holder and the keyless wallet tracking the multisig, for --multisig
+const COSIGNER_WALLET: &str = "Cosigner";
+const MULTISIG_WALLET: &str = "Multisig";
 
 // Node access params
 const RPC_URL: &str = "http://127.0.0.1:18443"; // Default regtest RPC port
@@ -18,116 +100,776 @@ const RPC_PASS: &str = "password";
 // You can use calls not provided in RPC lib API using the generic `call` function.
 // An example of using the `send` RPC call, which doesn't have exposed API.
 // You can also use serde_json `Deserialize` derivation to capture the returned json result.
-fn send(rpc: &Client, addr: &str) -> bitcoincore_rpc::Result<String> {
-    let args = [
-        json!([{addr : 100 }]), // recipient address
-        json!(null),            // conf target
-        json!(null),            // estimate mode
-        json!(null),            // fee rate in sats/vb
-        json!(null),            // Empty option object
-    ];
-
+// Arguments are passed by name (see `call_named`), so conf_target, estimate_mode, fee_rate and
+// options can simply be left out instead of being padded with nulls.
+fn send(
+    rpc: &Client,
+    addr: &str,
+    amount: Amount,
+    replaceable: bool,
+    fee_rate: Option<f64>,
+) -> Result<String, AppError> {
     #[derive(Deserialize)]
     struct SendResult {
         complete: bool,
         txid: String,
     }
-    let send_result = rpc.call::<SendResult>("send", &args)?;
-    assert!(send_result.complete);
+    let mut named = json!({ "outputs": [{ addr: amount.to_btc() }] });
+    if replaceable {
+        named["options"] = json!({ "replaceable": true });
+    }
+    if let Some(fee_rate) = fee_rate {
+        named["fee_rate"] = json!(fee_rate);
+    }
+    let send_result = call_named::<SendResult>(rpc, "send", SEND_PARAMS, named)?;
+    if !send_result.complete {
+        return Err(AppError::Check(format!(
+            "send left transaction {} incomplete",
+            send_result.txid
+        )));
+    }
     Ok(send_result.txid)
 }
 
-fn main() -> bitcoincore_rpc::Result<()> {
-    // Connect to Bitcoin Core RPC
-    let rpc = Client::new(
-        RPC_URL,
-        Auth::UserPass(RPC_USER.to_owned(), RPC_PASS.to_owned()),
-    )?;
+// Which wallet RPC makes the plain payment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendMethod {
+    // The classic single-recipient call, returning just the txid
+    SendToAddress,
+    // The newer `send`, taking an outputs list and returning a result object
+    Send,
+}
+
+impl FromStr for SendMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sendtoaddress" => Ok(SendMethod::SendToAddress),
+            "send" => Ok(SendMethod::Send),
+            _ => Err(format!(
+                "unknown send method {s} (expected send or sendtoaddress)"
+            )),
+        }
+    }
+}
+
+// Pay `amount` to `addr` with the chosen RPC. Both leave unset options to the wallet.
+fn perform_send(
+    rpc: &Client,
+    method: SendMethod,
+    addr: &str,
+    amount: Amount,
+    replaceable: bool,
+    fee_rate: Option<f64>,
+) -> Result<String, AppError> {
+    match method {
+        SendMethod::Send => send(rpc, addr, amount, replaceable, fee_rate),
+        SendMethod::SendToAddress => {
+            // Options left unset are dropped, so the plain send is just (address, amount)
+            let mut named = json!({ "address": addr, "amount": amount.to_btc() });
+            if replaceable {
+                named["replaceable"] = json!(true);
+            }
+            if let Some(fee_rate) = fee_rate {
+                named["fee_rate"] = json!(fee_rate);
+            }
+            call_named::<String>(rpc, "sendtoaddress", SENDTOADDRESS_PARAMS, named)
+        }
+    }
+}
+
+fn main() -> Result<(), AppError> {
+    let raw_args: Vec<String> = std::env::args().skip(1).collect();
+    // Whatever happens below, argument errors included, --status-file gets written when this
+    // goes out of scope
+    let mut status = StatusGuard::new(Args::status_file_from(&raw_args));
+    let result = Args::parse_from(raw_args).and_then(|args| run(&args, &mut status));
+    status.record(&result);
+    result
+}
+
+fn run(args: &Args, status: &mut StatusGuard) -> Result<(), AppError> {
+    let mut timer = PhaseTimer::default();
+    timer.start("connect");
+
+    // Connect to Bitcoin Core RPC, through a SOCKS5 proxy if one was given
+    let node = NodeConfig {
+        url: args.rpc_url.clone(),
+        user: args.rpc_user.clone(),
+        pass: args.rpc_pass.clone(),
+        proxy: args.proxy.clone(),
+    };
+    let rpc = node.client()?;
+
+    // --check-env stops after the preflight checks, before anything is created or mined
+    if args.check_env {
+        return check_env(&rpc, args.network, &args.out);
+    }
 
     // Get blockchain info
     let blockchain_info = rpc.get_blockchain_info()?;
     println!("Blockchain Info: {blockchain_info:?}");
 
+    // Some response fields depend on the Core release, so find out which one this is
+    let version = node_version(&rpc)?;
+    println!("Connected to Bitcoin Core {version}");
+
+    // A pruned node may have discarded the blocks we need to trace inputs back through
+    let pruned = blockchain_info.pruned;
+    if pruned {
+        eprintln!("Warning: node is pruned; tracing input addresses may be unavailable");
+    }
+
+    // Check up front that the node has the RPCs we are going to use
+    let capabilities = probe_capabilities(&rpc);
+    if capabilities.missing.is_empty() {
+        println!(
+            "Node supports all {} RPCs used here",
+            capabilities.available.len()
+        );
+    } else {
+        eprintln!(
+            "Warning: node lacks these RPCs: {}",
+            capabilities.missing.join(", ")
+        );
+    }
+    // Descriptor features can't work around a missing RPC, so refuse them before anything
+    // is created or mined
+    let descriptor_features = [
+        ("--seed", args.seed.is_some()),
+        ("--import-descriptor", args.import_descriptor.is_some()),
+        ("--mine-to-descriptor", args.mine_to_descriptor.is_some()),
+        ("--mine-to-xpub", args.mine_to_xpub.is_some()),
+        ("--multisig", args.multisig),
+    ];
+    for (feature, _) in descriptor_features.iter().filter(|(_, used)| *used) {
+        capabilities.require("getdescriptorinfo", feature)?;
+        capabilities.require("importdescriptors", feature)?;
+    }
+    // `send` is the newer wallet RPC; sendtoaddress makes the same payment on any release
+    let send_method = if args.send_method == SendMethod::Send && !capabilities.has("send") {
+        eprintln!("Warning: node has no send RPC, falling back to sendtoaddress");
+        SendMethod::SendToAddress
+    } else {
+        args.send_method
+    };
+
+    // --cleanup only tears down what earlier runs left behind
+    if args.cleanup {
+        let options = CleanupOptions {
+            delete: args.delete,
+            datadir: args.datadir.as_deref(),
+            reset_chain: args.reset_chain,
+            assume_yes: args.yes,
+        };
+        return cleanup(
+            &rpc,
+            &[
+                "Miner",
+                "Trader",
+                FUNDER_WALLET,
+                WATCH_ONLY_WALLET,
+                COSIGNER_WALLET,
+                MULTISIG_WALLET,
+            ],
+            args.network,
+            &options,
+        );
+    }
+
+    // --from-raw analyses an existing transaction instead of making one; nothing is mined
+    if let Some(path) = &args.from_raw {
+        let raw_hex = std::fs::read_to_string(path).map_err(|e| {
+            std::io::Error::new(e.kind(), format!("could not read {}: {e}", path.display()))
+        })?;
+        let report = report_from_raw(&rpc, &node.wallet_client("Miner")?, &raw_hex)?;
+        println!("Report for raw transaction {}", report.txid);
+        return write_report(&args.out, &report, args.format, args.trailing_newline);
+    }
+
+    // Refuse to pile more blocks onto a chain that is already deeper than the user expects
+    if let Some(max_height) = args.max_height {
+        if blockchain_info.blocks > max_height {
+            return Err(AppError::Check(format!(
+                "chain is already at height {} (above --max-height {max_height}); reset it first, e.g. with --cleanup --reset-chain",
+                blockchain_info.blocks
+            )));
+        }
+    }
+
     // ================= 1. Ensure both "Miner" and "Trader" wallets are available by creating or loading them=====================
+    timer.start("wallet setup");
+    // On a shared node other wallets may already be loaded alongside ours
+    let loaded = list_wallets(&rpc)?;
+    if loaded.is_empty() {
+        println!("No wallets loaded yet");
+    } else {
+        println!("Loaded wallets: {}", loaded.join(", "));
+    }
     for wallet in ["Miner", "Trader"] {
-        let response = rpc.call::<serde_json::Value>("createwallet", &[json!(wallet)]);
-        if let Err(e) = &response {
-            if !e.to_string().contains("already exists") {
-                panic!("Failed to create wallet: {e}");
-            }
-            // If the wallet already exists, attempt to load it in case it's not currently loaded
-            let _ = rpc.call::<serde_json::Value>("loadwallet", &[json!(wallet)]);
-        }
+        ensure_wallet(&rpc, wallet, &version)?;
     }
 
     // Initialize RPC clients for wallet-specific operations (Miner and Trader wallets)
-    let miner_rpc = Client::new(
-        &format!("{RPC_URL}/wallet/Miner"),
-        Auth::UserPass(RPC_USER.to_owned(), RPC_PASS.to_owned()),
-    )?;
+    let miner_rpc = node.wallet_client("Miner")?;
 
-    let trader_rpc = Client::new(
-        &format!("{RPC_URL}/wallet/Trader"),
-        Auth::UserPass(RPC_USER.to_owned(), RPC_PASS.to_owned()),
-    )?;
+    let trader_rpc = node.wallet_client("Trader")?;
+
+    // With --strict-wallet, make sure the wallets we just loaded belong to this node and network
+    if args.strict_wallet {
+        check_wallet_context(&miner_rpc, "Miner", args.network, &args.rpc_url)?;
+        check_wallet_context(&trader_rpc, "Trader", args.network, &args.rpc_url)?;
+    }
+
+    // Restore wallet keys from a canned scenario before generating any addresses
+    let wallets = [("Miner", &miner_rpc), ("Trader", &trader_rpc)];
+    if let Some(path) = &args.import_scenario {
+        import_scenario(&rpc, &wallets, path)?;
+    }
+
+    // With --seed, give each wallet fixed keys (its own BIP84 account) so addresses repeat
+    if let Some(seed) = &args.seed {
+        let seed = Vec::<u8>::from_hex(seed)
+            .map_err(|e| AppError::Usage(format!("--seed must be hex: {e}")))?;
+        for (account, (_, wallet_rpc)) in wallets.iter().enumerate() {
+            import_seed(wallet_rpc, &seed, account as u32, args.network)?;
+        }
+    }
+
+    // Optionally import a user-supplied descriptor into the Miner wallet
+    if let Some(descriptor) = &args.import_descriptor {
+        import_descriptor(&miner_rpc, descriptor)?;
+    }
+
+    // Addresses come out of the keypool, so check neither wallet has run dry
+    for (name, wallet_rpc) in &wallets {
+        ensure_keypool(wallet_rpc, name)?;
+    }
 
     //Generate a new address in the "Miner" wallet to receive mining rewards
-    let mining_address_str =
-        miner_rpc.call::<String>("getnewaddress", &[json!("Mining Reward")])?;
-    let mining_address = Address::from_str(&mining_address_str).map_err(|e| {
-        eprintln!("Address parse error: {e}");
-        RpcError::UnexpectedStructure
-    })?;
-    let mining_address = mining_address
-        .require_network(Network::Regtest)
-        .map_err(|e| {
-            eprintln!("Network error: {e}");
-            RpcError::UnexpectedStructure
-        })?;
+    let mining_address = new_address(&miner_rpc, "Mining Reward", args.network)?;
 
     // ============== 2. Generate initial balance by mining 103 blocks to the Miner address=====================
+    timer.start("mining");
     // 100 blocks for coinbase maturity + 3 for spendable balance
     // 103 blocks: Coinbase transactions require 100 confirmations before the mined BTC can be spent.
-    rpc.generate_to_address(103, &mining_address)?;
+    // Custom chains can use a different maturity (--maturity), so the count follows it.
+    // Keep the returned block hashes for the audit trail of what this run mined
+    println!("Using coinbase maturity of {} blocks", args.maturity);
+    let initial_blocks = args.maturity as u64 + 3;
+    // Blocks this run mines to addresses outside the Miner wallet. They count towards what the
+    // run mined but not towards the Miner's rewards.
+    let mut other_hashes = Vec::new();
+    // With --mine-to-xpub, first mine one block to an address derived from the xpub. The Miner
+    // wallet can't hold it (it has private keys, and refuses keyless imports), so a separate
+    // watch-only wallet watches it; the initial blocks below mature the reward.
+    let watch_only_rpc = match &args.mine_to_xpub {
+        Some(xpub) => {
+            let address = xpub_child_address(xpub, args.xpub_index, args.network)?;
+            ensure_watch_only_wallet(&rpc, WATCH_ONLY_WALLET, &version)?;
+            let watch_only_rpc = node.wallet_client(WATCH_ONLY_WALLET)?;
+            import_watch_only(&watch_only_rpc, &address)?;
+            println!(
+                "Mining 1 block to child {} of the xpub: {address}",
+                args.xpub_index
+            );
+            other_hashes.extend(mine_blocks(&rpc, 1, &address, false, args.max_tries)?);
+            Some(watch_only_rpc)
+        }
+        None => None,
+    };
+    // With --mine-to-descriptor the rewards go to that descriptor instead, which is imported
+    // into the Miner wallet first so it can spend them (so it needs its private keys)
+    let mut mined_hashes = match &args.mine_to_descriptor {
+        Some(descriptor) => {
+            let descriptor = mining_descriptor(&rpc, descriptor)?;
+            import_descriptor(&miner_rpc, &descriptor)?;
+            println!("Mining {initial_blocks} blocks to descriptor {descriptor}");
+            mine_to_descriptor(&rpc, initial_blocks, &descriptor, args.max_tries)?
+        }
+        // --mine-to-many puts the Miner first in the rotation, so the oldest (first mature)
+        // block is always its own and it has coins to send
+        None if !args.mine_to_many.is_empty() => {
+            let mut addresses = vec![mining_address.clone()];
+            for address in &args.mine_to_many {
+                addresses.push(parse_address(address, args.network)?);
+            }
+            println!(
+                "Mining {initial_blocks} blocks round-robin across {} addresses:",
+                addresses.len()
+            );
+            let per_address = mine_round_robin(&rpc, initial_blocks, &addresses, args.max_tries)?;
+            for (address, hashes) in addresses.iter().zip(&per_address) {
+                match mined_rewards(&rpc, hashes) {
+                    Ok(rewards) => println!(
+                        "  {address}: {} blocks, {} BTC in rewards",
+                        hashes.len(),
+                        rewards.to_btc()
+                    ),
+                    Err(e) => eprintln!("Warning: could not total the rewards of {address}: {e}"),
+                }
+            }
+            let mut per_address = per_address.into_iter();
+            let miner_hashes = per_address.next().unwrap_or_default();
+            other_hashes.extend(per_address.flatten());
+            miner_hashes
+        }
+        None => mine_blocks(
+            &rpc,
+            initial_blocks,
+            &mining_address,
+            args.show_progress,
+            args.max_tries,
+        )?,
+    };
+    if let Some(watch_only_rpc) = &watch_only_rpc {
+        let (balance, spendable) = watch_only_balance(watch_only_rpc)?;
+        println!(
+            "Wallet {WATCH_ONLY_WALLET} sees {} BTC of xpub rewards; watch-only, spendable: {spendable}",
+            balance.to_btc()
+        );
+    }
+
+    // Count the spendable coinbase outputs instead of assuming the initial blocks gave exactly 3
+    let mature = mature_coinbase_count(&miner_rpc, args.maturity)?;
+    println!("Mature (spendable) coinbase outputs in Miner wallet: {mature}");
+
+    // Now that the wallets are funded, optionally save them as a reproducible scenario
+    if let Some(path) = &args.export_scenario {
+        export_scenario(&rpc, &wallets, path, args.unsafe_export_keys)?;
+    }
 
     // =================== 3. Generate a receiving address in the Trader wallet=========================
-    let trader_address = trader_rpc.call::<String>("getnewaddress", &[json!("Trader Address")])?;
+    let trader_address = new_address(&trader_rpc, "Trader Address", args.network)?;
+
+    // ================= 4. send 20 BTC (or --amount) from Miner to Trader====================
+    timer.start("send");
+    // getnewaddress never hands out a used address of the wallet's own keys, but --seed makes
+    // every run derive the same sequence, so the seeded address may have been paid before
+    if args.seed.is_some() {
+        check_address_reuse(&trader_rpc, &trader_address.to_string())?;
+    }
 
-    // ================= 4. send 20 BTC from Miner to Trader====================
-    let txid = miner_rpc.call::<String>("sendtoaddress", &[json!(trader_address), json!(20.0)])?;
+    // Show what the node would estimate for the send in both estimation modes
+    let (_, conservative) = compare_fee_modes(&rpc, FEE_CONF_TARGET)?;
+
+    // With --fallback-fee-rate the send pays an explicit rate instead of the wallet default
+    let fee_rate = choose_fee_rate(conservative, args.fallback_fee_rate);
+
+    let amount = args.amount;
+
+    // An explicit change address must be for this network; it should also be the Miner's own
+    let change_address = match &args.change_address {
+        Some(address) => {
+            let address = parse_address(address, args.network)?.to_string();
+            // A user-chosen change address is the one most likely to have been used before.
+            // The wallet only tracks what its own addresses received.
+            if is_mine(&miner_rpc, &address)? {
+                check_address_reuse(&miner_rpc, &address)?;
+            } else {
+                eprintln!("Warning: change address {address} is not owned by the Miner wallet");
+            }
+            if args.multi_wallet_send {
+                eprintln!("Warning: --change-address is ignored by --multi-wallet-send");
+            }
+            Some(address)
+        }
+        None => None,
+    };
+    let address_type = trader_address
+        .address_type()
+        .map(|t| t.to_string())
+        .unwrap_or_default();
+    // Catch a dust-sized --amount here rather than as a confusing RPC rejection
+    check_dust(amount, &address_type)?;
+
+    // Make sure the Miner can afford the send before asking the wallet to build it
+    preflight_balance(&miner_rpc, amount, estimate_send_fee(fee_rate))?;
+
+    // Snapshot the Miner's UTXOs so we can see which ones coin selection picks
+    let utxos_before = list_unspent(&miner_rpc)?;
+
+    // Coin control: keep the --lock-utxo coins out of the send
+    let lock = UtxoLock::lock(&miner_rpc, &args.lock_utxos)?;
+    for (txid, vout) in &args.lock_utxos {
+        println!("Locked {txid}:{vout} so the send uses other coins");
+    }
+
+    // In dry-run mode the send is built and checked against the mempool rules, but never broadcast
+    if args.dry_run {
+        let hex = build_signed(
+            &miner_rpc,
+            &trader_address.to_string(),
+            amount,
+            change_address.as_deref(),
+            fee_rate,
+        )?;
+        let accept = test_accept(&rpc, &hex)?;
+        println!(
+            "Dry run: transaction {} allowed: {}",
+            accept.txid, accept.allowed
+        );
+        if let Some(reason) = &accept.reject_reason {
+            println!("Reject reason: {reason}");
+            if let Some(explanation) = explain_reject_reason(reason) {
+                println!("  {explanation}");
+            }
+        }
+        if let (Some(vsize), Some(fees)) = (accept.vsize, &accept.fees) {
+            println!(
+                "Node computes {vsize} vB paying {} BTC in fees",
+                fees.base.to_btc()
+            );
+        }
+        return Ok(());
+    }
+
+    // Multi-wallet mode needs a second, funded wallet to draw from
+    let funder_rpc = if args.multi_wallet_send {
+        ensure_wallet(&rpc, FUNDER_WALLET, &version)?;
+        let funder_rpc = node.wallet_client(FUNDER_WALLET)?;
+        let funder_address = new_address(&funder_rpc, "Funding", args.network)?;
+        miner_rpc.call::<String>(
+            "sendtoaddress",
+            &[json!(funder_address), json!(amount.to_btc())],
+        )?;
+        mined_hashes.extend(mine_blocks(
+            &rpc,
+            1,
+            &mining_address,
+            false,
+            args.max_tries,
+        )?);
+        println!("Funded wallet {FUNDER_WALLET} with {} BTC", amount.to_btc());
+        Some(funder_rpc)
+    } else {
+        None
+    };
+
+    // Counted right before sending so the check below only sees what the send itself added
+    let tx_count_before = wallet_tx_count(&miner_rpc)?;
+    let mempool_before = mempool_snapshot(&rpc)?;
+
+    if args.psbt && !capabilities.has("walletcreatefundedpsbt") {
+        eprintln!("Warning: node has no PSBT wallet RPCs, falling back to sendtoaddress");
+    }
+    // An explicit change address is normally passed to `send`; without it the PSBT workflow,
+    // which takes a change address too, is the way to honour it
+    let change_via_psbt = change_address.is_some() && !capabilities.has("send");
+    if change_via_psbt {
+        eprintln!("Warning: node has no send RPC, sending with a change address via a PSBT");
+    }
+    let use_psbt = (args.psbt || change_via_psbt) && capabilities.has("walletcreatefundedpsbt");
+
+    // In PSBT mode the same payment is built, signed and broadcast step by step
+    // The signed PSBT, kept for --artifacts-dir when the send went through the PSBT workflow
+    let mut signed_psbt = None;
+    let sent = if let Some(funder_rpc) = &funder_rpc {
+        // Draw from both the Miner and the funding wallet, each signing its own inputs
+        multi_wallet_send(
+            &[&miner_rpc, funder_rpc],
+            &trader_address.to_string(),
+            amount,
+        )
+    } else if use_psbt {
+        psbt_send(
+            &miner_rpc,
+            &trader_address.to_string(),
+            amount,
+            args.replaceable,
+            change_address.as_deref(),
+            fee_rate,
+            args.psbt_dir.as_deref(),
+        )
+        .map(|sent| {
+            signed_psbt = Some(sent.psbt);
+            sent.txid
+        })
+    } else if let Some(change_address) = &change_address {
+        send_with_change(
+            &miner_rpc,
+            &trader_address.to_string(),
+            amount,
+            change_address,
+            args.replaceable,
+            fee_rate,
+        )
+    } else {
+        perform_send(
+            &miner_rpc,
+            send_method,
+            &trader_address.to_string(),
+            amount,
+            args.replaceable,
+            fee_rate,
+        )
+    };
+    // Turn a rejection into an explanation before giving up
+    let txid = sent.inspect_err(|e| {
+        if let AppError::Rpc(rpc_error) = e {
+            eprintln!("Send failed: {}", explain_send_failure(rpc_error));
+        }
+    })?;
     println!("Transaction ID: {txid}");
+    let sent_at = Instant::now();
+    status.txid = Some(txid.clone());
+    assert_single_new_tx(&miner_rpc, tx_count_before)?;
+    lock.release()?;
 
     // ================ 5. Check if transaction is in the mempool=========================
-    let mempool_entry =
-        miner_rpc.call::<serde_json::Value>("getmempoolentry", &[json!(txid.clone())])?;
+    // Poll until the node has accepted the transaction rather than racing it with a single lookup
+    let mempool_entry = wait_for_mempool(&rpc, &txid, &args.wait, &version)?;
     println!("Mempool entry: {mempool_entry:?}");
+    let mempool_after = mempool_snapshot(&rpc)?;
+    println!(
+        "Mempool went from {} txs / {} vB to {} txs / {} vB ({:+} txs, {:+} vB)",
+        mempool_before.size,
+        mempool_before.bytes,
+        mempool_after.size,
+        mempool_after.bytes,
+        mempool_after.size as i64 - mempool_before.size as i64,
+        mempool_after.bytes as i64 - mempool_before.bytes as i64
+    );
+
+    // Optionally watch the mempool for anything else spending the same coins
+    if let Some(secs) = args.watch_double_spend {
+        let raw =
+            rpc.call::<serde_json::Value>("getrawtransaction", &[json!(txid), json!(true)])?;
+        let inputs = raw["vin"]
+            .as_array()
+            .map(|vin| {
+                vin.iter()
+                    .filter_map(|input| {
+                        Some((
+                            input["txid"].as_str()?.to_string(),
+                            input["vout"].as_u64()? as u32,
+                        ))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let watch = WaitConfig {
+            timeout: Duration::from_secs(secs),
+            ..args.wait
+        };
+        println!(
+            "Watching {} inputs for double-spends for {secs}s",
+            inputs.len()
+        );
+        match watch_double_spend(&rpc, &inputs, &txid, &watch)? {
+            Some(conflict) => eprintln!("Warning: conflicting spend detected: {conflict}"),
+            None => println!("No conflicting spend seen"),
+        }
+    }
+
+    // With --chain the Trader spends the payment on before it confirms (a parent/child pair)
+    let chained_txid = if args.chain {
+        let parent =
+            rpc.call::<serde_json::Value>("getrawtransaction", &[json!(txid), json!(true)])?;
+        let trader = trader_address.to_string();
+        let (vout, value) = parent["vout"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|out| out["scriptPubKey"]["address"].as_str() == Some(trader.as_str()))
+            .map(|out| {
+                (
+                    out["n"].as_u64().unwrap_or(0) as u32,
+                    out["value"].as_f64().unwrap_or(0.0),
+                )
+            })
+            .ok_or_else(|| AppError::Check(format!("no output of {txid} pays {trader}")))?;
+        let onward = new_address(&trader_rpc, "Chained", args.network)?.to_string();
+        let child_txid = chain_spend(&trader_rpc, &txid, vout, Amount::from_btc(value)?, &onward)?;
+        let parent_entry = fetch_mempool_entry(&rpc, &txid, &version)?;
+        let child_entry = fetch_mempool_entry(&rpc, &child_txid, &version)?;
+        println!("Chained spend {child_txid} spends {txid}:{vout} while both are unconfirmed");
+        println!(
+            "Parent: {} descendants, spent by {:?}; child: {} ancestors, depends on {:?}",
+            parent_entry.descendant_count,
+            parent_entry.spent_by,
+            child_entry.ancestor_count,
+            child_entry.depends
+        );
+        Some(child_txid)
+    } else {
+        None
+    };
 
     // ================ 6. Mine 1 block to confirm the transaction===========================
-    let _ = rpc.generate_to_address(1, &mining_address)?;
+    timer.start("confirm");
+    // Package rate: this transaction plus any unconfirmed descendants (e.g. the --chain child),
+    // which is what a miner weighs when child-pays-for-parent is in play
+    let package_entry = fetch_mempool_entry(&rpc, &txid, &version)?;
+    let package_fee_rate =
+        effective_fee_rate(package_entry.fees.descendant, package_entry.descendant_size);
+    // With --no-confirm the transaction stays in the mempool and the report says so
+    let confirmed = !args.no_confirm;
+    // --verify-utxo-set: the UTXO count before the confirming blocks, and where they start
+    if args.verify_utxo_set && !capabilities.has("gettxoutsetinfo") {
+        eprintln!("Warning: node has no gettxoutsetinfo RPC, skipping --verify-utxo-set");
+    }
+    let utxo_baseline = if confirmed && args.verify_utxo_set && capabilities.has("gettxoutsetinfo")
+    {
+        Some((utxo_set_size(&rpc)?, mined_hashes.len()))
+    } else {
+        None
+    };
+    let mut confirmation_wall = None;
+    if confirmed {
+        // One block by default; --confirmations keeps mining until the send is that deep
+        mined_hashes.extend(mine_to_confirmations(
+            &rpc,
+            &miner_rpc,
+            &txid,
+            args.confirmations,
+            &mining_address,
+            args.max_tries,
+        )?);
+        // Block-based and wall-clock confirmation side by side: regtest mines on demand, so a
+        // depth that takes roughly ten minutes per block elsewhere is reached almost at once
+        let wall = sent_at.elapsed();
+        println!(
+            "{}",
+            confirmation_summary(args.confirmations.max(1) as i64, wall)
+        );
+        confirmation_wall = Some(wall);
+
+        // Parent and child go into the same block
+        if let Some(child_txid) = &chained_txid {
+            let child =
+                trader_rpc.call::<serde_json::Value>("gettransaction", &[json!(child_txid)])?;
+            println!(
+                "Chained spend {child_txid} has {} confirmations",
+                child["confirmations"].as_i64().unwrap_or(0)
+            );
+        }
+
+        // Optionally bury the transaction under extra blocks. The mempool is empty again at this
+        // point, so these blocks carry nothing but their coinbase.
+        if args.mine_empty > 0 {
+            mined_hashes.extend(mine_blocks(
+                &rpc,
+                args.mine_empty,
+                &mining_address,
+                false,
+                args.max_tries,
+            )?);
+            let tx = miner_rpc.call::<serde_json::Value>("gettransaction", &[json!(txid)])?;
+            println!(
+                "Mined {} empty blocks; transaction now has {} confirmations",
+                args.mine_empty, tx["confirmations"]
+            );
+        }
+    } else {
+        println!("Leaving {txid} unconfirmed in the mempool");
+    }
+
+    // The node's UTXO set should have changed by exactly what the new blocks' transactions
+    // spent and created, their coinbases included
+    if let Some((before, first_block)) = utxo_baseline {
+        let after = utxo_set_size(&rpc)?;
+        let expected = blocks_utxo_delta(&rpc, &mined_hashes[first_block..])?;
+        let observed = after as i64 - before as i64;
+        if observed != expected {
+            return Err(AppError::Check(format!(
+                "UTXO set changed by {observed}, but the confirming blocks account for {expected}"
+            )));
+        }
+        println!("UTXO set went from {before} to {after} outputs ({observed:+}), as expected");
+    }
+
+    // Optionally record every block mined by this run and where its reward went. The xpub block
+    // and --mine-to-many blocks are listed too, though they stay out of the Miner's totals.
+    if let Some(path) = &args.blocks_out {
+        let all_hashes = [other_hashes.as_slice(), &mined_hashes].concat();
+        let blocks = report_mined_blocks(&rpc, &all_hashes)?;
+        write_mined_blocks(path, &blocks)?;
+        println!("Wrote {} mined blocks to {}", blocks.len(), path.display());
+    }
+
+    // Optionally knock the confirming block(s) off the chain and bring them back
+    if let Some(depth) = args.reorg.filter(|_| confirmed) {
+        simulate_reorg(&rpc, &miner_rpc, &txid, depth)?;
+    }
 
     // ============== 7. Retrieve and safely extract relevant transaction details from the Miner wallet=====================
-    let tx_info = miner_rpc.call::<serde_json::Value>(
-        "gettransaction",
-        &[json!(txid.clone()), json!(null), json!(true)],
-    )?;
-    let decoded = tx_info["decoded"].clone();
+    timer.start("report");
+    // An unconfirmed transaction is read from the mempool instead, and has no block yet
+    let (tx_info, decoded, fee) = if confirmed {
+        let tx_info = miner_rpc.call::<serde_json::Value>(
+            "gettransaction",
+            &[json!(txid.clone()), json!(null), json!(true)],
+        )?;
+        let decoded = decoded_tx(&miner_rpc, &tx_info, &txid)?;
+        // The wallet's `fee` nets the outputs against the Miner's own inputs only, so when
+        // --multi-wallet-send also spends the funder's coin it isn't the transaction's fee.
+        // The mempool entry taken before confirmation has the real one.
+        let fee = if funder_rpc.is_some() {
+            SignedAmount::ZERO - mempool_entry.fees.base.to_signed()?
+        } else {
+            SignedAmount::from_btc(tx_info["fee"].as_f64().unwrap_or(0.0))?
+        };
+        (tx_info, decoded, fee)
+    } else {
+        let hex = rpc.call::<String>("getrawtransaction", &[json!(txid)])?;
+        let decoded = rpc.call::<serde_json::Value>("decoderawtransaction", &[json!(hex)])?;
+        // Negated to match the wallet's sign convention for outgoing fees
+        let fee = SignedAmount::ZERO - mempool_entry.fees.base.to_signed()?;
+        (json!({ "hex": hex }), decoded, fee)
+    };
     let blockheight = tx_info["blockheight"].as_i64().unwrap_or(0);
-    let blockhash = tx_info["blockhash"].as_str().unwrap_or("unknown");
-    let fee = tx_info["fee"].as_f64().unwrap_or(0.0);
+    let confirmations = tx_info["confirmations"].as_i64().unwrap_or(0);
+    let blockhash = tx_info["blockhash"].as_str().unwrap_or("unconfirmed");
+    let vsize = decoded["vsize"].as_u64().unwrap_or(0);
+    let weight = decoded["weight"].as_u64().unwrap_or(0);
+    let size = decoded["size"].as_u64().unwrap_or(0);
 
     // Extract input transaction ID and output index from the decoded transaction
     let vin = decoded["vin"].as_array().unwrap();
     let input_txid = vin[0]["txid"].as_str().unwrap();
     let input_vout = vin[0]["vout"].as_u64().unwrap() as usize;
 
+    // Check the inputs' sequence numbers to see whether the transaction opted into RBF
+    let replaceable = signals_rbf(vin);
+    println!("Signals BIP125 replaceability: {replaceable}");
+    // Older nodes give their own verdict too (from before the send confirmed)
+    if let Some(reported) = mempool_entry.bip125_replaceable {
+        println!("Node reported bip125-replaceable: {reported}");
+    }
+    if args.replaceable && !replaceable {
+        eprintln!("Warning: --replaceable was set but no input signals replaceability");
+    }
+
+    // Report which of the Miner's coins the wallet's coin selection consumed
+    let consumed = consumed_utxos(&utxos_before, vin);
+    if let Some(utxo) = consumed
+        .iter()
+        .find(|u| args.lock_utxos.contains(&(u.txid.clone(), u.vout)))
+    {
+        return Err(AppError::Check(format!(
+            "locked coin {}:{} was spent anyway",
+            utxo.txid, utxo.vout
+        )));
+    }
+    println!(
+        "Coin selection spent {} of {} Miner UTXOs:",
+        consumed.len(),
+        utxos_before.len()
+    );
+    for utxo in &consumed {
+        println!(
+            "  {}:{} ({} BTC, {} confirmations)",
+            utxo.txid,
+            utxo.vout,
+            utxo.amount.to_btc(),
+            utxo.confirmations
+        );
+    }
+
     // Fetch the previous transaction to trace back the source of the input funds
-    let input_tx = miner_rpc.call::<serde_json::Value>(
-        "gettransaction",
-        &[json!(input_txid), json!(null), json!(true)],
-    )?;
-    let input_decoded = input_tx["decoded"].clone();
-    let input_vouts = input_decoded["vout"].as_array().unwrap();
-    let input_vout_obj = &input_vouts[input_vout];
+    let input_vout_obj = &trace_prevout(&miner_rpc, input_txid, input_vout, pruned, &utxos_before)?;
 
     // Extract the originating address of the input, falling back to script info if address is unavailable
     let miner_input_address = if let Some(addr_val) = input_vout_obj["scriptPubKey"].get("address")
@@ -147,47 +889,465 @@ fn main() -> bitcoincore_rpc::Result<()> {
             .to_string()
     };
 
-    let miner_input_amount = input_vout_obj["value"].as_f64().unwrap_or(0.0);
+    let miner_input_amount = Amount::from_btc(input_vout_obj["value"].as_f64().unwrap_or(0.0))?;
+
+    // Script type of the coin being spent; "unknown" when the node couldn't give us the prevout
+    // (pruned) and "nonstandard" for scripts that match no standard template
+    let miner_input_type = input_vout_obj["scriptPubKey"]["type"]
+        .as_str()
+        .unwrap_or("unknown")
+        .to_string();
+    println!("Spent output type: {miner_input_type}");
+
+    // If the input is a coinbase, show where its maturity window ended versus where it was spent
+    let input_tx = miner_rpc
+        .call::<serde_json::Value>(
+            "gettransaction",
+            &[json!(input_txid), json!(null), json!(true)],
+        )
+        .unwrap_or_default();
+    let coinbase_maturity_height = coinbase_maturity_height(&input_tx, args.maturity);
+    let input_depth_at_spend = input_tx["blockheight"]
+        .as_i64()
+        .filter(|_| confirmed)
+        .map(|input_height| (blockheight - input_height).max(0) as u64);
+    if let Some(depth) = input_depth_at_spend {
+        println!("Input was {depth} blocks deep when it was spent");
+    }
+    if let Some(mature_at) = coinbase_maturity_height.filter(|_| confirmed) {
+        println!(
+            "Input is a coinbase: spendable from height {mature_at}, spent at height {blockheight} ({} blocks after maturity)",
+            blockheight - mature_at as i64
+        );
+    }
+
+    // Resolve any further inputs too, so we know the total value going in
+    // Their transactions are fetched in one batch; any the batch can't supply are traced singly
+    let mut prevouts = vec![input_vout_obj.clone()];
+    let mut resolved_inputs = vec![(miner_input_address.clone(), miner_input_amount)];
+    let other_txids = vin[1..]
+        .iter()
+        .map(|input| input["txid"].as_str().unwrap_or_default().to_string())
+        .collect::<Vec<_>>();
+    let prevout_txs = fetch_txs_batch(&miner_rpc, &other_txids).unwrap_or_default();
+    for (i, input) in vin[1..].iter().enumerate() {
+        let txid = input["txid"].as_str().unwrap_or_default();
+        let vout = input["vout"].as_u64().unwrap_or_default() as usize;
+        let prevout = match prevout_txs.get(i) {
+            Some(tx) => tx["decoded"]["vout"][vout].clone(),
+            None => trace_prevout(&miner_rpc, txid, vout, pruned, &utxos_before)?,
+        };
+        let value = Amount::from_btc(prevout["value"].as_f64().unwrap_or(0.0))?;
+        let address = prevout["scriptPubKey"]["address"]
+            .as_str()
+            .unwrap_or("unknown");
+        resolved_inputs.push((address.to_string(), value));
+        prevouts.push(prevout);
+    }
+    if args.show_tree {
+        println!("{}", render_tx_tree(&decoded, &resolved_inputs));
+    }
 
     // Parse transaction outputs to identify recipient (Trader) and change (Miner) addresses and amounts
+    // Outputs are told apart by which wallet owns them, not by comparing address strings
     let vout = decoded["vout"].as_array().unwrap();
-    let mut trader_output_address = "";
-    let mut trader_output_amount = 0.0;
-    let mut miner_change_address = "";
-    let mut miner_change_amount = 0.0;
-
-    for out in vout {
-        // Safely extract the output amount
-        if let Some(value) = out.get("value").and_then(|v| v.as_f64()) {
-            // Safely extract the destination address, if available
-            if let Some(address_value) = out["scriptPubKey"].get("address") {
-                if let Some(address) = address_value.as_str() {
-                    if address == trader_address {
-                        // This is the recipient (Trader) output
-                        trader_output_address = address;
-                        trader_output_amount = value;
-                    } else if address != trader_address {
-                        // Any address that's not the Trader's is treated as change (likely to Miner)
-                        miner_change_address = address;
-                        miner_change_amount = value;
-                    }
-                }
+    let miner_outputs = classify_outputs(&miner_rpc, vout)?;
+    let trader_outputs = classify_outputs(&trader_rpc, vout)?;
+    println!(
+        "Miner owns outputs {:?}; outputs {:?} are external to it",
+        miner_outputs.mine, miner_outputs.external
+    );
+    let ownership = (0..vout.len())
+        .map(|i| Ownership {
+            trader: trader_outputs.mine.contains(&i),
+            miner: miner_outputs.mine.contains(&i),
+        })
+        .collect::<Vec<_>>();
+    let roles = assign_output_roles(vout, &ownership, amount);
+    let position = |index: Option<usize>| index.map_or("none".to_string(), |i| i.to_string());
+    println!(
+        "Payment is output {}, change is output {} (change position is randomized)",
+        position(roles.payment),
+        position(roles.change)
+    );
+
+    // Outputs beyond payment and change aren't part of the report; say where they came from
+    let miner_avoids_reuse = avoid_reuse(&miner_rpc)?;
+    println!("Miner wallet avoid_reuse: {miner_avoids_reuse}");
+    let extra_outputs = (0..vout.len())
+        .filter(|&i| Some(i) != roles.payment && Some(i) != roles.change)
+        .count();
+    if extra_outputs > 0 {
+        let hint = if miner_avoids_reuse {
+            " (avoid_reuse is on, so coin selection may have split the change)"
+        } else {
+            ""
+        };
+        println!("{extra_outputs} output(s) besides payment and change{hint}");
+    }
+    let output_at = |index: Option<usize>| -> Result<(&str, Amount), AppError> {
+        let Some(out) = index.map(|i| &vout[i]) else {
+            return Ok(("", Amount::ZERO));
+        };
+        let address = out["scriptPubKey"]["address"].as_str().unwrap_or_default();
+        Ok((
+            address,
+            Amount::from_btc(out["value"].as_f64().unwrap_or(0.0))?,
+        ))
+    };
+    let (trader_output_address, trader_output_amount) = output_at(roles.payment)?;
+    let (miner_change_address, miner_change_amount) = output_at(roles.change)?;
+
+    // Inputs must equal outputs plus fee, otherwise something above was mis-parsed
+    let (input_total, outputs_total) = value_totals(&prevouts, vout)?;
+
+    // Compare the wallet's fee with the one implied by the values we parsed. Normally they agree
+    // exactly; anything more than a satoshi apart points at a parsing or precision problem.
+    let computed_fee = input_total.to_signed()? - outputs_total.to_signed()?;
+    let fee_discrepancy = fee.abs() - computed_fee;
+    if fee_discrepancy.abs() > SignedAmount::ONE_SAT {
+        eprintln!(
+            "Warning: wallet fee {} BTC differs from computed fee {} BTC by {} sats",
+            fee.abs().to_btc(),
+            computed_fee.to_btc(),
+            fee_discrepancy.to_sat()
+        );
+    }
+    verify_value_balance(input_total, outputs_total, fee.abs().to_unsigned()?)?;
+    println!(
+        "Inputs {} BTC - outputs {} BTC = fee {} BTC",
+        input_total.to_btc(),
+        outputs_total.to_btc(),
+        fee.abs().to_btc()
+    );
+
+    // A custom send may embed data in an OP_RETURN output; show it as hex and, if printable, text
+    let op_return_hex = op_return_data(vout);
+    if let Some(hex) = &op_return_hex {
+        let text = Vec::<u8>::from_hex(hex).unwrap_or_default();
+        println!(
+            "OP_RETURN data: {hex} ({:?})",
+            String::from_utf8_lossy(&text)
+        );
+    }
+
+    // The wallet reports the fee as a negative amount; the rate uses its magnitude.
+    // weight is in weight units (4 per non-witness byte, 1 per witness byte), vsize = weight / 4.
+    let fee_units = fee_in_units(fee.abs().to_unsigned()?, vsize);
+    let effective_rate = fee_units.sat_per_vb;
+    println!("Size: {vsize} vB ({weight} WU), effective fee rate: {effective_rate:.2} sat/vB");
+    println!(
+        "Fee: {} BTC = {} sats = {effective_rate:.2} sat/vB",
+        fee_units.btc.to_btc(),
+        fee_units.sats
+    );
+    println!("Package fee rate (with descendants): {package_fee_rate:.2} sat/vB");
+
+    // Witness bytes weigh 1 WU instead of 4, so a segwit tx's vsize is smaller than its byte size.
+    // The stripped (non-witness) size follows from weight = 3 * stripped + total.
+    let segwit = is_segwit(vin);
+    let witness_discount = witness_discount_vbytes(size, vsize);
+    let stripped_size = weight.saturating_sub(size) / 3;
+    println!(
+        "Segwit: {segwit}; {size} bytes total, {stripped_size} bytes stripped, witness discount saves {witness_discount} vB"
+    );
+
+    // Recompute the sizes from the raw bytes and make sure they agree with what the node says
+    let raw_hex = tx_info["hex"].as_str().unwrap_or_default();
+    verify_txid(raw_hex, &txid)?;
+    let local = size_breakdown(raw_hex)?;
+    println!(
+        "Local size breakdown: {} bytes base, {} bytes total, {} vB, {} WU",
+        local.base_size, local.total_size, local.vsize, local.weight
+    );
+    for (what, ours, node) in [
+        ("base size", local.base_size, stripped_size),
+        ("total size", local.total_size, size),
+        ("vsize", local.vsize, vsize),
+        ("weight", local.weight, weight),
+    ] {
+        if ours != node {
+            eprintln!("Warning: local {what} {ours} differs from the node's {node}");
+        }
+    }
+
+    // Each input removes an output from the UTXO set and each spendable output adds one
+    let (utxos_spent, utxos_created) = utxo_impact(vin, vout);
+    println!(
+        "UTXO set impact: {utxos_spent} spent, {utxos_created} created ({:+} net)",
+        utxos_created as i64 - utxos_spent as i64
+    );
+
+    // Version 2 allows relative locktimes; Core sets nLockTime to the tip height against fee sniping
+    let tx_version = decoded["version"].as_u64().unwrap_or(0) as u32;
+    let tx_locktime = decoded["locktime"].as_u64().unwrap_or(0) as u32;
+    println!("Version {tx_version}, locktime {tx_locktime}");
+
+    // Where in the wallets' BIP32 trees the two addresses were derived
+    let key_path = |rpc: &Client, address: String| {
+        hd_key_path(rpc, &address).unwrap_or_else(|e| {
+            eprintln!("Warning: could not look up the HD key path of {address}: {e}");
+            "none".to_string()
+        })
+    };
+    let mining_address_hdkeypath = key_path(&miner_rpc, mining_address.to_string());
+    let trader_address_hdkeypath = key_path(&trader_rpc, trader_address.to_string());
+    println!(
+        "HD key paths: mining address {mining_address_hdkeypath}, trader address {trader_address_hdkeypath}"
+    );
+
+    // How much of what went in came back to the Miner as change
+    let change_ratio = change_ratio(miner_change_amount, input_total);
+    println!("Change ratio: {change_ratio:.4}");
+
+    // The confirming block, and where in it the send landed. In an otherwise empty regtest block
+    // that is index 1, right after the coinbase.
+    let confirming_block = if confirmed {
+        Some(block_report(&rpc, blockhash)?)
+    } else {
+        None
+    };
+    let block_tx_index = confirming_block.as_ref().and_then(|b| b.tx_index(&txid));
+    if let (Some(block), Some(index)) = (&confirming_block, block_tx_index) {
+        println!(
+            "Transaction is number {index} of {} in its block",
+            block.n_tx
+        );
+    }
+
+    let report = TxReport {
+        txid: txid.clone(),
+        miner_input_address,
+        miner_input_amount,
+        miner_input_type,
+        trader_output_address: trader_output_address.to_string(),
+        trader_output_amount,
+        miner_change_address: miner_change_address.to_string(),
+        miner_change_amount,
+        fee,
+        confirmed,
+        confirmations,
+        blockheight,
+        blockhash: blockhash.to_string(),
+        size,
+        vsize,
+        weight,
+        fee_btc: fee_units.btc,
+        fee_sats: fee_units.sats,
+        fee_rate: effective_rate,
+        package_fee_rate,
+        replaceable,
+        op_return_hex,
+        fee_discrepancy,
+        is_segwit: segwit,
+        witness_discount_vbytes: witness_discount,
+        change_ratio,
+        coinbase_maturity_height,
+        input_depth_at_spend,
+        mining_address_hdkeypath,
+        trader_address_hdkeypath,
+        tx_version,
+        tx_locktime,
+        total_input_value: input_total,
+        total_output_value: outputs_total,
+        utxos_spent,
+        utxos_created,
+        block_tx_index,
+        trader_vout: roles.payment,
+        change_vout: roles.change,
+        confirmation_wall_secs: confirmation_wall.map(|d| d.as_secs_f64()),
+    };
+
+    // The payment should be a usable coin in the Trader wallet, not just a record. With --chain
+    // the Trader has already spent it on, so there is nothing left to find.
+    if !args.chain {
+        assert_trader_utxo(&trader_rpc, &txid, trader_output_amount)?;
+    }
+
+    // Change should have landed exactly where it was directed
+    if let Some(expected) = &change_address {
+        if report.miner_change_address != *expected {
+            eprintln!(
+                "Warning: change went to {} instead of the requested {expected}",
+                report.miner_change_address
+            );
+        }
+    }
+
+    // ========== 8. Write all extracted transaction details to ../out.txt (or --out) in the required output format==============
+    write_report(&args.out, &report, args.format, args.trailing_newline)?;
+
+    // Everything from here on is informational: the report is already written, so a failed
+    // lookup or check below still fails the run but no longer costs the user out.txt
+
+    // Make sure the confirming block is on the best chain and not a stale branch
+    let status = if confirmed {
+        chain_status(&rpc, blockhash, blockheight as u64)
+    } else {
+        Ok("active".to_string())
+    };
+    match status {
+        Ok(status) if status == "active" => {}
+        Ok(status) => eprintln!(
+            "Warning: confirming block {blockhash} is not on the active chain (status: {status})"
+        ),
+        Err(e) => eprintln!("Warning: could not check the chain status of {blockhash}: {e}"),
+    }
+
+    // Show how the transaction is anchored: the confirming block's header fields
+    if let Some(block) = confirming_block.as_ref().filter(|_| args.block_details) {
+        println!(
+            "Confirming block {} at height {}: {} transactions, merkle root {}, nonce {}, bits {}",
+            block.hash, block.height, block.n_tx, block.merkleroot, block.nonce, block.bits
+        );
+    }
+
+    // The raw 80-byte header, optionally saved, and what each of its fields holds
+    if let Some(path) = args.header_out.as_ref().filter(|_| confirmed) {
+        let header_hex = block_header_hex(&rpc, blockhash)?;
+        let header = parse_block_header(&header_hex)?;
+        println!(
+            "Block header: version {:#x}, prev {}, merkle root {}, time {}, bits {:#010x}, nonce {}",
+            header.version.to_consensus(),
+            header.prev_blockhash,
+            header.merkle_root,
+            header.time,
+            header.bits.to_consensus(),
+            header.nonce
+        );
+        std::fs::write(path, &header_hex)?;
+    }
+
+    // Tie it together: what the Miner mined, paid and kept, versus what the Trader gained
+    let subsidy = check_subsidy(
+        &rpc,
+        &[other_hashes.as_slice(), &mined_hashes].concat(),
+        args.expected_subsidy,
+        args.network,
+    )?;
+    println!(
+        "Block subsidies of this run's blocks check out: {} BTC",
+        subsidy.to_btc()
+    );
+    let mined = mined_rewards(&rpc, &mined_hashes)?;
+    let net = net_position(mined, trader_output_amount, fee.abs().to_unsigned()?);
+    println!(
+        "Miner mined {} BTC, sent {} BTC and paid {} BTC in fees: net {} BTC; Trader gained {} BTC",
+        mined.to_btc(),
+        trader_output_amount.to_btc(),
+        fee.abs().to_btc(),
+        net.to_btc(),
+        trader_output_amount.to_btc()
+    );
+
+    // A persistent Miner wallet accumulates fees across runs; show the running total and the
+    // wallet's fixed fee setting (paytxfee, 0 when the wallet estimates fees itself)
+    let paytxfee = miner_rpc.call::<serde_json::Value>("getwalletinfo", &[])?["paytxfee"]
+        .as_f64()
+        .unwrap_or(0.0);
+    println!(
+        "Miner wallet lifetime fees: {} BTC (paytxfee {paytxfee} BTC/kvB)",
+        lifetime_fees(&miner_rpc)?.to_btc()
+    );
+
+    // Optionally keep everything the run produced about the transaction in one place
+    if let Some(dir) = &args.artifacts_dir {
+        let block = if confirmed {
+            Some(rpc.call::<serde_json::Value>("getblock", &[json!(blockhash), json!(1)])?)
+        } else {
+            None
+        };
+        let written = write_artifacts(
+            dir,
+            raw_hex,
+            &decoded,
+            &report,
+            block.as_ref(),
+            signed_psbt.as_deref(),
+        )?;
+        println!("Wrote {} to {}", written.join(", "), dir.display());
+    }
+
+    // --verify-roundtrip: what was written must parse back to what we meant to write
+    if args.verify_roundtrip {
+        if args.format == OutputFormat::Text {
+            let parsed = parse_report_file(&args.out)?;
+            if parsed != text_contract(&report) {
+                return Err(AppError::Check(format!(
+                    "{} does not parse back to the report that was written",
+                    args.out.display()
+                )));
             }
+            println!("{} round-trips through the parser", args.out.display());
+        } else {
+            eprintln!("Warning: --verify-roundtrip only checks the text format, skipping");
         }
     }
 
-    // ========== 8. Write all extracted transaction details to ../out.txt in the required output format==============
-    let mut file = File::create("../out.txt").expect("Unable to create out.txt");
-    writeln!(file, "{txid}")?;
-    writeln!(file, "{miner_input_address}")?;
-    writeln!(file, "{miner_input_amount}")?;
-    writeln!(file, "{trader_output_address}")?;
-    writeln!(file, "{trader_output_amount}")?;
-    writeln!(file, "{miner_change_address}")?;
-    writeln!(file, "{miner_change_amount}")?;
-    writeln!(file, "{fee}")?;
-    writeln!(file, "{blockheight}")?;
-    writeln!(file, "{blockhash}")?;
+    // With --history, tell the story of the run from both wallets' point of view
+    if args.history {
+        let history = wallet_history(&wallets)?;
+        println!("Wallet history ({} entries):", history.len());
+        print!("{}", render_timeline(&history));
+    }
+
+    // --multisig: the Miner, a cosigner and the Trader share a 2-of-3 multisig; the Miner and
+    // cosigner sign a spend from it to the Trader
+    if args.multisig {
+        ensure_wallet(&rpc, COSIGNER_WALLET, &version)?;
+        ensure_watch_only_wallet(&rpc, MULTISIG_WALLET, &version)?;
+        let cosigner_rpc = node.wallet_client(COSIGNER_WALLET)?;
+        let multisig_rpc = node.wallet_client(MULTISIG_WALLET)?;
+        let to = new_address(&trader_rpc, "Multisig payment", args.network)?;
+        multisig_demo(
+            &rpc,
+            &[
+                ("Miner", &miner_rpc),
+                (COSIGNER_WALLET, &cosigner_rpc),
+                ("Trader", &trader_rpc),
+            ],
+            &multisig_rpc,
+            &to,
+            &mining_address,
+            &MultisigOptions {
+                amount,
+                network: args.network,
+                max_tries: args.max_tries,
+            },
+        )?;
+    }
+
+    // --loop carries on sending from the now-funded wallets until Ctrl-C
+    if let Some(secs) = args.loop_interval {
+        let options = LoopOptions {
+            interval: Duration::from_secs(secs),
+            amount,
+            network: args.network,
+            send_method,
+            fee_rate,
+            max_tries: args.max_tries,
+            log: &args.loop_log,
+        };
+        let completed = send_loop(&rpc, &miner_rpc, &trader_rpc, &mining_address, &options)?;
+        println!("Loop stopped after {completed} iterations");
+    }
+
+    timer.finish();
+    println!("Time per phase:\n{}", timer.breakdown());
+
+    if let Some(path) = &args.metrics_file {
+        let metrics = Metrics {
+            run_duration_seconds: timer.total().as_secs_f64(),
+            blocks_mined: (other_hashes.len() + mined_hashes.len()) as u64,
+            send_amount_sats: amount.to_sat(),
+            tx_fee_sats: report.fee_sats,
+            tx_vsize_vbytes: report.vsize,
+            tx_fee_rate_sat_per_vbyte: report.fee_rate,
+            tx_confirmations: report.confirmations,
+        };
+        write_metrics(path, &metrics)?;
+        println!("Wrote metrics to {}", path.display());
+    }
 
     Ok(())
 }
@@ -0,0 +1,120 @@
+use crate::error::AppError;
+use crate::rpc::NodeVersion;
+use crate::wait::WaitConfig;
+use bitcoincore_rpc::json::GetMempoolEntryResultFees;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+// The parts of a `getmempoolentry` result we use. Not the library's GetMempoolEntryResult: that
+// requires `bip125-replaceable`, which newer nodes no longer send.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MempoolEntry {
+    pub fees: GetMempoolEntryResultFees,
+    // Counts and virtual sizes include the transaction itself
+    #[serde(rename = "descendantcount")]
+    pub descendant_count: u64,
+    #[serde(rename = "descendantsize")]
+    pub descendant_size: u64,
+    #[serde(rename = "ancestorcount")]
+    pub ancestor_count: u64,
+    // Unconfirmed parents, and unconfirmed children spending this transaction
+    pub depends: Vec<String>,
+    #[serde(rename = "spentby")]
+    pub spent_by: Vec<String>,
+    // The node's own BIP125 verdict (which also counts signalling inherited from unconfirmed
+    // ancestors); None on releases that no longer report it
+    #[serde(skip)]
+    pub bip125_replaceable: Option<bool>,
+}
+
+// `getmempoolentry` for `txid`, reading the fields this node's release provides
+pub fn fetch_mempool_entry(
+    rpc: &Client,
+    txid: &str,
+    version: &NodeVersion,
+) -> Result<MempoolEntry, AppError> {
+    let raw = rpc.call::<Value>("getmempoolentry", &[json!(txid)])?;
+    let mut entry = MempoolEntry::deserialize(&raw).map_err(|e| {
+        AppError::Check(format!("getmempoolentry for {txid} is missing fields: {e}"))
+    })?;
+    entry.bip125_replaceable = version
+        .mempool_replaceable_field()
+        .and_then(|field| raw[field].as_bool());
+    Ok(entry)
+}
+
+// Poll `getrawmempool` until `txid` shows up, then fetch its mempool entry.
+// A single `getmempoolentry` right after the send can race the node processing it, so we wait
+// for the txid to appear first. If it never does (e.g. the node rejected it) we time out.
+pub fn wait_for_mempool(
+    rpc: &Client,
+    txid: &str,
+    wait: &WaitConfig,
+    version: &NodeVersion,
+) -> Result<MempoolEntry, AppError> {
+    let what = format!("transaction {txid} did not appear in the mempool, was it rejected?");
+    wait.poll(&what, || {
+        let mempool = rpc.call::<Vec<String>>("getrawmempool", &[])?;
+        if mempool.iter().any(|t| t == txid) {
+            return Ok(Some(fetch_mempool_entry(rpc, txid, version)?));
+        }
+        Ok(None)
+    })
+}
+
+// The node-wide mempool totals from `getmempoolinfo`
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct MempoolInfo {
+    // Number of transactions
+    pub size: u64,
+    // Sum of their virtual sizes
+    pub bytes: u64,
+}
+
+pub fn mempool_snapshot(rpc: &Client) -> Result<MempoolInfo, AppError> {
+    Ok(rpc.call::<MempoolInfo>("getmempoolinfo", &[])?)
+}
+
+// Look for a transaction other than `original_txid` spending `outpoint`. Only the mempool is
+// searched (`gettxspendingprevout`, Core 24+): once a spend is confirmed the node keeps no
+// index of who spent what.
+pub fn detect_conflict(
+    rpc: &Client,
+    outpoint: (String, u32),
+    original_txid: &str,
+) -> Result<Option<String>, AppError> {
+    let (txid, vout) = outpoint;
+    let spends = rpc.call::<Vec<serde_json::Value>>(
+        "gettxspendingprevout",
+        &[json!([{ "txid": txid, "vout": vout }])],
+    )?;
+    Ok(spends
+        .iter()
+        .filter_map(|spend| spend["spendingtxid"].as_str())
+        .find(|spender| *spender != original_txid)
+        .map(str::to_string))
+}
+
+// Keep checking every input of `txid` for a conflicting spend until `wait.timeout` runs out.
+// Returns the first conflicting txid, or None if the watch ended quietly.
+pub fn watch_double_spend(
+    rpc: &Client,
+    inputs: &[(String, u32)],
+    txid: &str,
+    wait: &WaitConfig,
+) -> Result<Option<String>, AppError> {
+    let watched = wait.poll("no double-spend seen", || {
+        for outpoint in inputs {
+            if let Some(conflict) = detect_conflict(rpc, outpoint.clone(), txid)? {
+                return Ok(Some(conflict));
+            }
+        }
+        Ok(None)
+    });
+    match watched {
+        Ok(conflict) => Ok(Some(conflict)),
+        Err(AppError::Timeout(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
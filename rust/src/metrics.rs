@@ -0,0 +1,70 @@
+use crate::error::AppError;
+use std::fs;
+use std::path::Path;
+
+// The handful of numbers worth scraping from an automated run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub run_duration_seconds: f64,
+    pub blocks_mined: u64,
+    pub send_amount_sats: u64,
+    pub tx_fee_sats: u64,
+    pub tx_vsize_vbytes: u64,
+    pub tx_fee_rate_sat_per_vbyte: f64,
+    pub tx_confirmations: i64,
+}
+
+// Write `metrics` in the Prometheus text exposition format, e.g. for node_exporter's textfile
+// collector. Every metric is a gauge describing the latest run, with a `capstone_` prefix.
+pub fn write_metrics(path: &Path, metrics: &Metrics) -> Result<(), AppError> {
+    let gauges: [(&str, &str, String); 7] = [
+        (
+            "run_duration_seconds",
+            "Wall-clock duration of the run",
+            metrics.run_duration_seconds.to_string(),
+        ),
+        (
+            "blocks_mined",
+            "Blocks mined by the run",
+            metrics.blocks_mined.to_string(),
+        ),
+        (
+            "send_amount_sats",
+            "Amount sent from Miner to Trader",
+            metrics.send_amount_sats.to_string(),
+        ),
+        (
+            "tx_fee_sats",
+            "Fee paid by the send",
+            metrics.tx_fee_sats.to_string(),
+        ),
+        (
+            "tx_vsize_vbytes",
+            "Virtual size of the send",
+            metrics.tx_vsize_vbytes.to_string(),
+        ),
+        (
+            "tx_fee_rate_sat_per_vbyte",
+            "Effective fee rate of the send",
+            metrics.tx_fee_rate_sat_per_vbyte.to_string(),
+        ),
+        (
+            "tx_confirmations",
+            "Confirmations the send had at report time",
+            metrics.tx_confirmations.to_string(),
+        ),
+    ];
+    let mut text = String::new();
+    for (name, help, value) in gauges {
+        text.push_str(&format!(
+            "# HELP capstone_{name} {help}\n# TYPE capstone_{name} gauge\ncapstone_{name} {value}\n"
+        ));
+    }
+    fs::write(path, text).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("could not write metrics to {}: {e}", path.display()),
+        )
+    })?;
+    Ok(())
+}
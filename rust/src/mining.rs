@@ -0,0 +1,384 @@
+use crate::error::AppError;
+use crate::wallet::list_unspent;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde_json::json;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+// Coinbase outputs can only be spent once they have this many confirmations
+pub const COINBASE_MATURITY: u32 = 100;
+
+// Subsidy of the first blocks, before any halving
+const INITIAL_SUBSIDY_BTC: u64 = 50;
+
+// Blocks per generatetoaddress call when progress is shown
+const PROGRESS_CHUNK: u64 = 10;
+
+// Blocks mine_to_confirmations may mine beyond its target before giving up on the transaction
+const CONFIRMATION_SLACK: u64 = 3;
+
+// One generatetoaddress call. `max_tries` caps the nonces tried per call (Core's default is
+// 1,000,000); when the cap is hit the node returns fewer blocks than asked for, which is an
+// error here since every caller counts on the blocks it asked for.
+fn generate(
+    rpc: &Client,
+    count: u64,
+    address: &Address,
+    max_tries: Option<u64>,
+) -> Result<Vec<String>, AppError> {
+    let mut params = vec![json!(count), json!(address)];
+    if let Some(max_tries) = max_tries {
+        params.push(json!(max_tries));
+    }
+    let hashes = rpc.call::<Vec<String>>("generatetoaddress", &params)?;
+    all_mined(hashes, count)
+}
+
+// The node stops early, returning fewer hashes, when a call runs out of tries
+fn all_mined(hashes: Vec<String>, count: u64) -> Result<Vec<String>, AppError> {
+    if (hashes.len() as u64) < count {
+        return Err(AppError::Check(format!(
+            "mined only {} of {count} blocks before running out of tries; raise --max-tries",
+            hashes.len()
+        )));
+    }
+    Ok(hashes)
+}
+
+// Mine `count` blocks to `address` and return their hashes. With `show_progress` the blocks are
+// mined in chunks with a progress line after each; the resulting chain is the same either way.
+pub fn mine_blocks(
+    rpc: &Client,
+    count: u64,
+    address: &Address,
+    show_progress: bool,
+    max_tries: Option<u64>,
+) -> Result<Vec<String>, AppError> {
+    let chunk = if show_progress { PROGRESS_CHUNK } else { count };
+    let mut hashes = Vec::with_capacity(count as usize);
+    while (hashes.len() as u64) < count {
+        let n = chunk.min(count - hashes.len() as u64);
+        hashes.extend(generate(rpc, n, address, max_tries)?);
+        if show_progress {
+            println!("mined {}/{count} blocks", hashes.len());
+        }
+    }
+    Ok(hashes)
+}
+
+// Mine `count` blocks one at a time, each paying the next address in `addresses` in turn.
+// Returns the hashes mined to each address, in the same order as `addresses`.
+pub fn mine_round_robin(
+    rpc: &Client,
+    count: u64,
+    addresses: &[Address],
+    max_tries: Option<u64>,
+) -> Result<Vec<Vec<String>>, AppError> {
+    let mut hashes = vec![Vec::new(); addresses.len()];
+    for i in 0..count as usize {
+        let slot = i % addresses.len();
+        hashes[slot].extend(generate(rpc, 1, &addresses[slot], max_tries)?);
+    }
+    Ok(hashes)
+}
+
+// Mine `count` blocks whose coinbase pays the script of `descriptor` (which must carry its
+// checksum and not be ranged). Returns their hashes.
+pub fn mine_to_descriptor(
+    rpc: &Client,
+    count: u64,
+    descriptor: &str,
+    max_tries: Option<u64>,
+) -> Result<Vec<String>, AppError> {
+    let mut params = vec![json!(count), json!(descriptor)];
+    if let Some(max_tries) = max_tries {
+        params.push(json!(max_tries));
+    }
+    let hashes = rpc.call::<Vec<String>>("generatetodescriptor", &params)?;
+    all_mined(hashes, count)
+}
+
+// Count the wallet's spendable coinbase outputs, i.e. block rewards with at least `maturity`
+// confirmations. `listunspent` doesn't say whether an output came from a
+// coinbase, so each entry's transaction is looked up for the `generated` flag.
+pub fn mature_coinbase_count(rpc: &Client, maturity: u32) -> Result<u32, AppError> {
+    let mut count = 0;
+    for utxo in list_unspent(rpc)? {
+        if utxo.confirmations < maturity {
+            continue;
+        }
+        let tx = rpc.call::<serde_json::Value>("gettransaction", &[json!(utxo.txid)])?;
+        if tx["generated"].as_bool().unwrap_or(false) {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+// Confirmation count of a wallet transaction, and whether it's sitting in the mempool
+fn tx_state(rpc: &Client, wallet_rpc: &Client, txid: &str) -> Result<(i64, bool), AppError> {
+    let tx = wallet_rpc.call::<serde_json::Value>("gettransaction", &[json!(txid)])?;
+    let confirmations = tx["confirmations"].as_i64().unwrap_or(0);
+    let mempool = rpc.call::<Vec<String>>("getrawmempool", &[])?;
+    Ok((confirmations, mempool.iter().any(|t| t == txid)))
+}
+
+// Mine blocks to `address` until the wallet transaction `txid` has at least `target`
+// confirmations (at least one; --no-confirm is the way to skip mining). The first block
+// confirms it, each further one adds a confirmation. A transaction that was evicted or
+// conflicted never gets there, so at most CONFIRMATION_SLACK blocks beyond the target are
+// mined before this fails. Returns the hashes of the blocks mined.
+pub fn mine_to_confirmations(
+    rpc: &Client,
+    wallet_rpc: &Client,
+    txid: &str,
+    target: u64,
+    address: &Address,
+    max_tries: Option<u64>,
+) -> Result<Vec<String>, AppError> {
+    let target = target.max(1);
+    let limit = target + CONFIRMATION_SLACK;
+    let mut hashes = Vec::new();
+    loop {
+        let (confirmations, in_mempool) = tx_state(rpc, wallet_rpc, txid)?;
+        if confirmations >= target as i64 {
+            return Ok(hashes);
+        }
+        let mined = hashes.len() as u64;
+        if mined >= limit {
+            return Err(AppError::Check(format!(
+                "{txid} still has {confirmations} confirmation(s) after mining {mined} blocks \
+                 (in mempool: {in_mempool}); was it evicted or replaced?"
+            )));
+        }
+        let needed = (target - confirmations.max(0) as u64).min(limit - mined);
+        hashes.extend(generate(rpc, needed, address, max_tries)?);
+    }
+}
+
+// Simulate a reorg of `depth` blocks: invalidate the tip blocks (their transactions fall back to
+// the mempool), then reconsider them so the original chain is restored. The transaction's
+// confirmation count is logged at each step, and the final chain state is reported.
+pub fn simulate_reorg(
+    rpc: &Client,
+    wallet_rpc: &Client,
+    txid: &str,
+    depth: u64,
+) -> Result<(), AppError> {
+    let tip_height = rpc.get_block_count()?;
+    if depth == 0 || depth > tip_height {
+        return Err(AppError::Usage(format!(
+            "--reorg depth must be between 1 and the chain height ({tip_height})"
+        )));
+    }
+    let original_tip = rpc.get_best_block_hash()?;
+    let (confirmations, _) = tx_state(rpc, wallet_rpc, txid)?;
+    println!("Reorg: tx has {confirmations} confirmation(s) at height {tip_height}");
+
+    // Invalidating the lowest block to disconnect also disconnects everything built on it
+    let fork_point = rpc.get_block_hash(tip_height - depth + 1)?;
+    rpc.invalidate_block(&fork_point)?;
+    let (confirmations, in_mempool) = tx_state(rpc, wallet_rpc, txid)?;
+    println!(
+        "Reorg: invalidated {depth} block(s), height now {}; tx has {confirmations} confirmation(s), in mempool: {in_mempool}",
+        rpc.get_block_count()?
+    );
+
+    // Reconsider the disconnected blocks so the original chain becomes active again
+    rpc.reconsider_block(&fork_point)?;
+    let (confirmations, in_mempool) = tx_state(rpc, wallet_rpc, txid)?;
+    let tip = rpc.get_best_block_hash()?;
+    println!(
+        "Reorg: reconsidered blocks, height now {}; tx has {confirmations} confirmation(s), in mempool: {in_mempool}",
+        rpc.get_block_count()?
+    );
+    if tip == original_tip {
+        println!("Reorg: original chain restored (tip {tip})");
+    } else {
+        eprintln!("Warning: reorg left the chain on a different tip ({tip}, was {original_tip})");
+    }
+    Ok(())
+}
+
+// A block this run mined and where its coinbase reward went
+#[derive(Debug, Clone)]
+pub struct MinedBlock {
+    pub hash: String,
+    pub height: u64,
+    pub coinbase_address: String,
+}
+
+// Look up each block mined by this run (hashes as returned by generatetoaddress)
+pub fn report_mined_blocks(rpc: &Client, hashes: &[String]) -> Result<Vec<MinedBlock>, AppError> {
+    hashes
+        .iter()
+        .map(|hash| {
+            // Verbosity 2 includes the decoded transactions, the first of which is the coinbase
+            let block = rpc.call::<serde_json::Value>("getblock", &[json!(hash), json!(2)])?;
+            let coinbase_address = block["tx"][0]["vout"][0]["scriptPubKey"]["address"]
+                .as_str()
+                .unwrap_or("unknown")
+                .to_string();
+            Ok(MinedBlock {
+                hash: hash.clone(),
+                height: block["height"].as_u64().unwrap_or(0),
+                coinbase_address,
+            })
+        })
+        .collect()
+}
+
+// Total coinbase value (subsidy plus collected fees) of the given blocks
+pub fn mined_rewards(rpc: &Client, hashes: &[String]) -> Result<Amount, AppError> {
+    let mut total = Amount::ZERO;
+    for hash in hashes {
+        let block = rpc.call::<serde_json::Value>("getblock", &[json!(hash), json!(2)])?;
+        for out in block["tx"][0]["vout"].as_array().into_iter().flatten() {
+            total += Amount::from_btc(out["value"].as_f64().unwrap_or(0.0))?;
+        }
+    }
+    Ok(total)
+}
+
+// Blocks between subsidy halvings; regtest halves every 150 blocks to make halvings testable
+pub fn halving_interval(network: Network) -> u64 {
+    match network {
+        Network::Regtest => 150,
+        _ => 210_000,
+    }
+}
+
+// The consensus block subsidy at `height`: 50 BTC, halved every `interval` blocks
+pub fn standard_subsidy(height: u64, interval: u64) -> Amount {
+    let halvings = height / interval;
+    if halvings >= 64 {
+        return Amount::ZERO;
+    }
+    Amount::from_int_btc(INITIAL_SUBSIDY_BTC) / 2u64.pow(halvings as u32)
+}
+
+// A block's subsidy, checked: what its coinbase claimed less the fees of the transactions it
+// carries (the raw coinbase value would overstate it for every block that confirmed a send),
+// compared with `expected` or, when that isn't given, the standard subsidy for `height`. With
+// the fees taken out the comparison is exact, with no tolerance: both sides are whole satoshis,
+// so any difference is a real mismatch in chain parameters rather than rounding.
+fn verified_subsidy(
+    coinbase_value: Amount,
+    fees: Amount,
+    height: u64,
+    interval: u64,
+    expected: Option<Amount>,
+) -> Result<Amount, AppError> {
+    let subsidy = coinbase_value.checked_sub(fees).unwrap_or(Amount::ZERO);
+    let want = expected.unwrap_or_else(|| standard_subsidy(height, interval));
+    // A coinbase may claim less than it's allowed, but a mismatch on our own regtest blocks
+    // means the chain's parameters aren't what we assumed
+    if subsidy != want {
+        return Err(AppError::Check(format!(
+            "block {height} has a subsidy of {} BTC, expected {} BTC; pass \
+             --expected-subsidy for chains with custom parameters",
+            subsidy.to_btc(),
+            want.to_btc()
+        )));
+    }
+    Ok(subsidy)
+}
+
+// Check every given block's subsidy (see verified_subsidy) against `expected` or the standard
+// subsidy for its height. Custom regtest chains can change the subsidy, hence the override.
+// Returns the total subsidy.
+pub fn check_subsidy(
+    rpc: &Client,
+    hashes: &[String],
+    expected: Option<Amount>,
+    network: Network,
+) -> Result<Amount, AppError> {
+    let mut total = Amount::ZERO;
+    for hash in hashes {
+        let block = rpc.call::<serde_json::Value>("getblock", &[json!(hash), json!(2)])?;
+        let txs = block["tx"].as_array().cloned().unwrap_or_default();
+        let mut coinbase_value = Amount::ZERO;
+        for out in txs
+            .first()
+            .and_then(|tx| tx["vout"].as_array())
+            .into_iter()
+            .flatten()
+        {
+            coinbase_value += Amount::from_btc(out["value"].as_f64().unwrap_or(0.0))?;
+        }
+        let height = block["height"].as_u64().unwrap_or(0);
+        // Per-transaction fees need the block's undo data (and Core 23+); without them the
+        // subsidy can't be separated from the fees
+        let mut fees = Amount::ZERO;
+        let mut fees_known = true;
+        for tx in txs.iter().skip(1) {
+            match tx["fee"].as_f64() {
+                Some(fee) => fees += Amount::from_btc(fee)?,
+                None => fees_known = false,
+            }
+        }
+        if !fees_known {
+            eprintln!("Warning: node gave no fees for block {height}, skipping its subsidy check");
+            continue;
+        }
+        total += verified_subsidy(
+            coinbase_value,
+            fees,
+            height,
+            halving_interval(network),
+            expected,
+        )?;
+    }
+    Ok(total)
+}
+
+// One line per block: height, hash, coinbase address
+pub fn write_mined_blocks(path: &Path, blocks: &[MinedBlock]) -> Result<(), AppError> {
+    let mut file = File::create(path)?;
+    for block in blocks {
+        writeln!(
+            file,
+            "{} {} {}",
+            block.height, block.hash, block.coinbase_address
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsidy_halves_every_interval() {
+        let interval = halving_interval(Network::Regtest);
+        assert_eq!(standard_subsidy(0, interval), Amount::from_int_btc(50));
+        assert_eq!(standard_subsidy(149, interval), Amount::from_int_btc(50));
+        assert_eq!(standard_subsidy(150, interval), Amount::from_int_btc(25));
+        assert_eq!(standard_subsidy(64 * interval, interval), Amount::ZERO);
+    }
+
+    #[test]
+    fn block_carrying_a_fee_still_has_the_standard_subsidy() {
+        // The block confirming the send: its coinbase also claims the 141 sat fee
+        let interval = halving_interval(Network::Regtest);
+        let coinbase_value = Amount::from_sat(5_000_000_141);
+        let subsidy = verified_subsidy(coinbase_value, Amount::from_sat(141), 104, interval, None);
+        assert_eq!(subsidy.unwrap(), Amount::from_int_btc(50));
+    }
+
+    #[test]
+    fn subsidy_off_the_chain_parameters_is_a_mismatch() {
+        let interval = halving_interval(Network::Regtest);
+        // Past the first regtest halving the coinbase may only claim 25 BTC
+        let coinbase_value = Amount::from_int_btc(50);
+        let err = verified_subsidy(coinbase_value, Amount::ZERO, 150, interval, None).unwrap_err();
+        assert!(matches!(err, AppError::Check(_)));
+        // ...unless the chain was started with a custom subsidy
+        let expected = Some(Amount::from_int_btc(50));
+        let subsidy = verified_subsidy(coinbase_value, Amount::ZERO, 150, interval, expected);
+        assert_eq!(subsidy.unwrap(), Amount::from_int_btc(50));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::descriptor::{add_descriptor_checksum, import_descriptors};
+use crate::error::AppError;
+use crate::mining::mine_blocks;
+use crate::wallet::new_address;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde_json::{json, Value};
+
+// Signatures needed out of the three keys
+const REQUIRED_SIGNATURES: usize = 2;
+
+// A key expression for a fresh key of `rpc`'s wallet, with its origin
+// ([fingerprint/path]pubkey). The origin ends up in the PSBT's BIP32 derivations, which is how
+// each signing wallet recognises its own key in a script it has never seen.
+fn cosigner_key(rpc: &Client, network: Network) -> Result<String, AppError> {
+    let address = new_address(rpc, "Multisig key", network)?;
+    let info = rpc.call::<Value>("getaddressinfo", &[json!(address)])?;
+    let field = |name: &str| {
+        info[name].as_str().map(str::to_string).ok_or_else(|| {
+            AppError::Descriptor(format!("getaddressinfo gave no {name} for {address}"))
+        })
+    };
+    let pubkey = field("pubkey")?;
+    let fingerprint = field("hdmasterfingerprint")?;
+    let path = field("hdkeypath")?;
+    Ok(format!(
+        "[{fingerprint}{}]{pubkey}",
+        path.trim_start_matches('m')
+    ))
+}
+
+// Settings for --multisig
+pub struct MultisigOptions {
+    // What the first signer pays into the multisig; half of it is then spent on
+    pub amount: Amount,
+    pub network: Network,
+    // Nonces tried per block, as for every other block the run mines
+    pub max_tries: Option<u64>,
+}
+
+// Demonstrate a 2-of-3 multisig end to end. Each of `signers` (wallet name and client)
+// contributes one key to a wsh(multi(2,...)) descriptor, which `watcher` (a wallet without
+// private keys) imports so it can track and spend from the address. The first signer funds it
+// with `options.amount`, then the watcher builds a PSBT paying half of that to `to`, the first
+// two signers add their signatures in turn, and the finalized transaction is broadcast and
+// confirmed. Returns the spending txid.
+pub fn multisig_demo(
+    rpc: &Client,
+    signers: &[(&str, &Client)],
+    watcher: &Client,
+    to: &Address,
+    mining_address: &Address,
+    options: &MultisigOptions,
+) -> Result<String, AppError> {
+    let MultisigOptions {
+        amount,
+        network,
+        max_tries,
+    } = *options;
+    let mut keys = Vec::new();
+    for (_, signer) in signers {
+        keys.push(cosigner_key(signer, network)?);
+    }
+    let descriptor = add_descriptor_checksum(
+        rpc,
+        &format!("wsh(multi({REQUIRED_SIGNATURES},{}))", keys.join(",")),
+    )?;
+    import_descriptors(
+        watcher,
+        vec![json!({ "desc": descriptor, "timestamp": "now" })],
+    )?;
+    let address = rpc
+        .call::<Vec<String>>("deriveaddresses", &[json!(descriptor)])?
+        .pop()
+        .ok_or_else(|| AppError::Descriptor(format!("{descriptor} derives no address")))?;
+    println!(
+        "{REQUIRED_SIGNATURES}-of-{} multisig address: {address}",
+        signers.len()
+    );
+
+    // Fund it and confirm the funding so the watcher sees a spendable coin
+    let (funder, funder_rpc) = signers
+        .first()
+        .ok_or_else(|| AppError::Check("multisig needs signers".to_string()))?;
+    let funding_txid =
+        funder_rpc.call::<String>("sendtoaddress", &[json!(address), json!(amount.to_btc())])?;
+    mine_blocks(rpc, 1, mining_address, false, max_tries)?;
+    println!(
+        "{funder} funded the multisig with {} BTC in {funding_txid}",
+        amount.to_btc()
+    );
+
+    // The watcher knows the script but no keys, so it can only build the unsigned PSBT. The
+    // descriptor isn't ranged, so change goes back to the multisig address itself.
+    let payment = (amount / 2).to_btc();
+    let funded = watcher.call::<Value>(
+        "walletcreatefundedpsbt",
+        &[
+            json!([]),
+            json!([{ to.to_string(): payment }]),
+            json!(0),
+            json!({ "changeAddress": address }),
+        ],
+    )?;
+    let mut psbt = funded["psbt"]
+        .as_str()
+        .ok_or_else(|| AppError::Psbt("walletcreatefundedpsbt returned no PSBT".to_string()))?
+        .to_string();
+    println!("Step 1: watch-only wallet created an unsigned PSBT paying {payment} BTC to {to}");
+
+    // Each signer adds its signature; the PSBT is complete once enough of them have signed
+    let mut complete = false;
+    for (step, (name, signer)) in signers.iter().take(REQUIRED_SIGNATURES).enumerate() {
+        let processed = signer.call::<Value>("walletprocesspsbt", &[json!(psbt)])?;
+        psbt = processed["psbt"].as_str().unwrap_or_default().to_string();
+        complete = processed["complete"].as_bool().unwrap_or(false);
+        println!(
+            "Step {}: {name} signed ({} of {REQUIRED_SIGNATURES}), complete: {complete}",
+            step + 2,
+            step + 1
+        );
+    }
+    if !complete {
+        return Err(AppError::Psbt(format!(
+            "multisig PSBT still incomplete after {REQUIRED_SIGNATURES} signers; \
+             did each wallet recognise its key?"
+        )));
+    }
+
+    let finalized = rpc.call::<Value>("finalizepsbt", &[json!(psbt)])?;
+    let hex = match (finalized["complete"].as_bool(), finalized["hex"].as_str()) {
+        (Some(true), Some(hex)) => hex.to_string(),
+        _ => {
+            return Err(AppError::Psbt(
+                "finalizepsbt could not complete the multisig transaction".to_string(),
+            ))
+        }
+    };
+    let txid = rpc.call::<String>("sendrawtransaction", &[json!(hex)])?;
+    mine_blocks(rpc, 1, mining_address, false, max_tries)?;
+    println!(
+        "Step {}: finalized and broadcast {txid}, confirmed in the next block",
+        REQUIRED_SIGNATURES + 2
+    );
+    Ok(txid)
+}
@@ -0,0 +1,76 @@
+use crate::error::AppError;
+use crate::rpc::probe_capabilities;
+use bitcoincore_rpc::bitcoin::Network;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+// Quick environment check for --check-env: can we reach the node, is it on the expected
+// network, does it have our RPCs, and can the report be written? Nothing is created on the
+// node. Prints a checklist and fails if any check did.
+pub fn check_env(rpc: &Client, network: Network, out: &Path) -> Result<(), AppError> {
+    let mut checks: Vec<(String, Result<(), String>)> = Vec::new();
+
+    let chain = rpc
+        .call::<Value>("getblockchaininfo", &[])
+        .map(|info| info["chain"].as_str().unwrap_or_default().to_string());
+    let connected = chain.is_ok();
+    checks.push((
+        "node reachable".to_string(),
+        chain.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+    ));
+    if let Ok(chain) = &chain {
+        let expected = network.to_core_arg();
+        let on_network = if chain == expected {
+            Ok(())
+        } else {
+            Err(format!("node is on {chain}"))
+        };
+        checks.push((format!("node on {expected}"), on_network));
+    }
+    if connected {
+        let capabilities = probe_capabilities(rpc);
+        let all_rpcs = if capabilities.missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("missing {}", capabilities.missing.join(", ")))
+        };
+        checks.push(("required RPCs available".to_string(), all_rpcs));
+    }
+    checks.push((
+        format!("{} writable", out.display()),
+        check_writable(out).map_err(|e| e.to_string()),
+    ));
+
+    for (check, result) in &checks {
+        match result {
+            Ok(()) => println!("[ok]   {check}"),
+            Err(e) => println!("[FAIL] {check}: {e}"),
+        }
+    }
+    let failed = checks.iter().filter(|(_, r)| r.is_err()).count();
+    if failed > 0 {
+        return Err(AppError::Check(format!(
+            "{failed} environment check(s) failed"
+        )));
+    }
+    println!("Environment looks good");
+    Ok(())
+}
+
+// Probe with a scratch file next to `path` rather than touching an existing report. Missing
+// directories would be created when the report is written, so the probe goes into the nearest
+// one that already exists; a check shouldn't leave directories behind.
+fn check_writable(path: &Path) -> std::io::Result<()> {
+    // A relative path's ancestors end in "", which isn't a directory, hence the "." fallback
+    let dir = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .find(|dir| dir.is_dir())
+        .unwrap_or(Path::new("."));
+    let probe = dir.join(".capstone-write-check");
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
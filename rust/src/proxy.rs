@@ -0,0 +1,160 @@
+use bitcoincore_rpc::jsonrpc::{self, Request, Response, Transport};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// A minimal JSON-RPC transport that tunnels each HTTP request through a SOCKS5 proxy (e.g. Tor).
+// The node's hostname is handed to the proxy unresolved, so .onion addresses work too.
+pub struct Socks5Transport {
+    proxy: String,
+    host: String,
+    port: u16,
+    path: String,
+    basic_auth: String,
+}
+
+impl Socks5Transport {
+    // `proxy` is host:port of the SOCKS5 server, `url` the node's http:// RPC URL
+    pub fn new(proxy: &str, url: &str, user: &str, pass: &str) -> io::Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not an http:// URL: {url}"),
+            )
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("bad port in {url}"))
+                })?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+        Ok(Socks5Transport {
+            proxy: proxy.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            basic_auth: base64::encode(format!("{user}:{pass}")),
+        })
+    }
+
+    // Open a TCP stream to the node through the proxy (RFC 1928, no proxy authentication)
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect(&self.proxy)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        // Greeting: version 5, one method offered, "no authentication"
+        stream.write_all(&[0x05, 0x01, 0x00])?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        if reply != [0x05, 0x00] {
+            return Err(socks_error("proxy refused the no-authentication method"));
+        }
+
+        // CONNECT to the node by domain name so the proxy does the resolving
+        let host = self.host.as_bytes();
+        let host_len = u8::try_from(host.len()).map_err(|_| socks_error("hostname is too long"))?;
+        let mut request = vec![0x05, 0x01, 0x00, 0x03, host_len];
+        request.extend_from_slice(host);
+        request.extend_from_slice(&self.port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header)?;
+        if header[1] != 0x00 {
+            return Err(socks_error(&format!(
+                "proxy could not connect to {}:{} (reply code {})",
+                self.host, self.port, header[1]
+            )));
+        }
+        // Skip the bound address the proxy reports back, then the port
+        let addr_len = match header[3] {
+            0x01 => 4,
+            0x04 => 16,
+            0x03 => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len)?;
+                usize::from(len[0])
+            }
+            _ => return Err(socks_error("unknown address type in proxy reply")),
+        };
+        let mut skip = vec![0u8; addr_len + 2];
+        stream.read_exact(&mut skip)?;
+        Ok(stream)
+    }
+
+    // POST a JSON body and return the response body. HTTP/1.0 keeps the response un-chunked
+    // and lets us read until the node closes the connection.
+    fn post<B: serde::Serialize, R: serde::de::DeserializeOwned>(
+        &self,
+        body: &B,
+    ) -> Result<R, jsonrpc::Error> {
+        let body = serde_json::to_vec(body)?;
+        let mut stream = self.connect().map_err(transport_error)?;
+        let head = format!(
+            "POST {} HTTP/1.0\r\nHost: {}:{}\r\nAuthorization: Basic {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            self.path,
+            self.host,
+            self.port,
+            self.basic_auth,
+            body.len()
+        );
+        stream
+            .write_all(head.as_bytes())
+            .and_then(|_| stream.write_all(&body))
+            .map_err(transport_error)?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).map_err(transport_error)?;
+        let split = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| transport_error(socks_error("malformed HTTP response")))?;
+        let payload = &response[split + 4..];
+        if payload.is_empty() {
+            // e.g. 401 Unauthorized comes back without a JSON body
+            let status = String::from_utf8_lossy(&response[..split]);
+            let status = status.lines().next().unwrap_or_default().to_string();
+            return Err(transport_error(socks_error(&format!(
+                "empty response from node: {status}"
+            ))));
+        }
+        Ok(serde_json::from_slice(payload)?)
+    }
+}
+
+impl Transport for Socks5Transport {
+    fn send_request(&self, req: Request) -> Result<Response, jsonrpc::Error> {
+        self.post(&req)
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+        self.post(&reqs)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "http://{}:{}{} via socks5://{}",
+            self.host, self.port, self.path, self.proxy
+        )
+    }
+}
+
+fn socks_error(msg: &str) -> io::Error {
+    io::Error::other(msg.to_string())
+}
+
+fn transport_error(e: io::Error) -> jsonrpc::Error {
+    jsonrpc::Error::Transport(Box::new(e))
+}
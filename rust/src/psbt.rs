@@ -0,0 +1,111 @@
+use crate::error::AppError;
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct FundedPsbt {
+    psbt: String,
+    fee: f64,
+}
+
+#[derive(Deserialize)]
+struct ProcessedPsbt {
+    psbt: String,
+    complete: bool,
+}
+
+#[derive(Deserialize)]
+struct FinalizedPsbt {
+    hex: Option<String>,
+    complete: bool,
+}
+
+// What a PSBT send produced: the broadcast transaction and the signed PSBT it came from
+pub struct PsbtSent {
+    pub txid: String,
+    pub psbt: String,
+}
+
+// Send `amount` to `address` using the PSBT workflow instead of `sendtoaddress`:
+// the wallet funds an unsigned PSBT, signs it, and the finalized transaction is broadcast.
+// Each intermediate PSBT is logged, and written to `out_dir` when one is given.
+// Change goes to `change_address` if given, otherwise to a fresh wallet change address, and
+// `fee_rate` (sat/vB) overrides the wallet's fee choice when set.
+pub fn psbt_send(
+    rpc: &Client,
+    address: &str,
+    amount: Amount,
+    replaceable: bool,
+    change_address: Option<&str>,
+    fee_rate: Option<f64>,
+    out_dir: Option<&Path>,
+) -> Result<PsbtSent, AppError> {
+    if let Some(dir) = out_dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    // 1. Create: the wallet picks inputs and adds a change output, but signs nothing yet.
+    // Only override the wallet's RBF default when replaceability was explicitly requested.
+    let mut options = json!({});
+    if replaceable {
+        options["replaceable"] = json!(true);
+    }
+    if let Some(change_address) = change_address {
+        options["changeAddress"] = json!(change_address);
+    }
+    if let Some(fee_rate) = fee_rate {
+        options["fee_rate"] = json!(fee_rate);
+    }
+    let funded = rpc.call::<FundedPsbt>(
+        "walletcreatefundedpsbt",
+        &[
+            json!([]),
+            json!([{ address: amount.to_btc() }]),
+            json!(0),
+            options,
+        ],
+    )?;
+    println!("Created PSBT (fee {} BTC): {}", funded.fee, funded.psbt);
+    save(out_dir, "created.psbt", &funded.psbt)?;
+
+    // 2. Process: the wallet adds the signatures for the inputs it owns
+    let processed = rpc.call::<ProcessedPsbt>("walletprocesspsbt", &[json!(funded.psbt)])?;
+    println!("Signed PSBT: {}", processed.psbt);
+    save(out_dir, "signed.psbt", &processed.psbt)?;
+    if !processed.complete {
+        return Err(AppError::Psbt(
+            "walletprocesspsbt could not sign every input".to_string(),
+        ));
+    }
+
+    // 3. Finalize: build the final scriptSig/witness and extract the network transaction
+    let finalized = rpc.call::<FinalizedPsbt>("finalizepsbt", &[json!(&processed.psbt)])?;
+    let hex = match (finalized.complete, finalized.hex) {
+        (true, Some(hex)) => hex,
+        _ => {
+            return Err(AppError::Psbt(
+                "finalizepsbt did not produce a complete transaction".to_string(),
+            ))
+        }
+    };
+    println!("Finalized transaction: {hex}");
+    save(out_dir, "final.hex", &hex)?;
+
+    // 4. Broadcast the extracted transaction
+    let txid = rpc.call::<String>("sendrawtransaction", &[json!(hex)])?;
+    Ok(PsbtSent {
+        txid,
+        psbt: processed.psbt,
+    })
+}
+
+fn save(out_dir: Option<&Path>, name: &str, contents: &str) -> Result<(), AppError> {
+    if let Some(dir) = out_dir {
+        fs::write(dir.join(name), contents)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,412 @@
+use crate::analysis::{
+    change_ratio, dust_limit, effective_fee_rate, fee_in_units, is_segwit, op_return_data,
+    signals_rbf, utxo_impact, value_totals, witness_discount_vbytes,
+};
+use crate::error::AppError;
+use crate::report::TxReport;
+use crate::wallet::{classify_outputs, decoded_tx, list_unspent, Utxo};
+use bitcoincore_rpc::bitcoin::consensus::deserialize;
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::bitcoin::{Amount, SignedAmount, Transaction};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+// Fee rate for hand-built transactions, where no wallet does the fee estimation for us
+const RAW_FEE_RATE_SAT_VB: u64 = 2;
+
+// Rough vsize of a P2WPKH transaction: overhead, ~68 vB per input, ~31 vB per output
+pub fn estimate_vsize(inputs: usize, outputs: usize) -> u64 {
+    11 + 68 * inputs as u64 + 31 * outputs as u64
+}
+
+#[derive(Deserialize)]
+struct SignResult {
+    hex: String,
+    complete: bool,
+}
+
+// Pay `amount` to `to` from the UTXOs of several wallets in one transaction.
+// At least one coin is taken from every wallet, more as needed to cover amount and fee; the
+// change goes back to the first wallet. The unsigned transaction is passed from wallet to
+// wallet and each one signs only the inputs it owns.
+pub fn multi_wallet_send(
+    clients: &[&Client],
+    to: &str,
+    amount: Amount,
+) -> Result<String, AppError> {
+    let (first, _) = clients
+        .split_first()
+        .ok_or_else(|| AppError::Usage("multi_wallet_send needs at least one wallet".into()))?;
+
+    // Largest coins first, one from each wallet up front
+    let mut pools = Vec::new();
+    for rpc in clients {
+        let mut utxos = list_unspent(rpc)?;
+        utxos.sort_by_key(|u| std::cmp::Reverse(u.amount));
+        pools.push(utxos.into_iter());
+    }
+    let mut selected: Vec<Utxo> = Vec::new();
+    for (i, pool) in pools.iter_mut().enumerate() {
+        let utxo = pool.next().ok_or_else(|| {
+            AppError::Check(format!("funding wallet #{} has no spendable coins", i + 1))
+        })?;
+        selected.push(utxo);
+    }
+    let fee_for = |inputs: usize| Amount::from_sat(estimate_vsize(inputs, 2) * RAW_FEE_RATE_SAT_VB);
+    let total = |coins: &[Utxo]| coins.iter().map(|u| u.amount).sum::<Amount>();
+    let mut remaining = pools.into_iter().flatten();
+    while total(&selected) < amount + fee_for(selected.len()) {
+        let utxo = remaining.next().ok_or_else(|| {
+            AppError::Check(format!(
+                "wallets hold {} BTC, not enough for {} BTC plus fee",
+                total(&selected).to_btc(),
+                amount.to_btc()
+            ))
+        })?;
+        selected.push(utxo);
+    }
+
+    // Change back to the first wallet, unless it would be dust (then it's left as fee)
+    let fee = fee_for(selected.len());
+    let change = total(&selected) - amount - fee;
+    let mut outputs = serde_json::Map::new();
+    outputs.insert(to.to_string(), json!(amount.to_btc()));
+    if change >= dust_limit("p2wpkh") {
+        let change_address = first.call::<String>("getrawchangeaddress", &[])?;
+        outputs.insert(change_address, json!(change.to_btc()));
+    }
+    let inputs = selected
+        .iter()
+        .map(|u| json!({ "txid": u.txid, "vout": u.vout }))
+        .collect::<Vec<_>>();
+    let mut hex = first.call::<String>("createrawtransaction", &[json!(inputs), json!(outputs)])?;
+    println!(
+        "Built transaction spending {} coins from {} wallets (fee {} BTC)",
+        selected.len(),
+        clients.len(),
+        fee.to_btc()
+    );
+
+    // Each wallet adds signatures for its own inputs and leaves the others alone
+    let mut complete = false;
+    for (i, rpc) in clients.iter().enumerate() {
+        let signed = rpc.call::<SignResult>("signrawtransactionwithwallet", &[json!(hex)])?;
+        hex = signed.hex;
+        complete = signed.complete;
+        println!("Wallet #{} signed its inputs (complete: {complete})", i + 1);
+    }
+    if !complete {
+        return Err(AppError::Check(
+            "transaction is still missing signatures after every wallet signed".to_string(),
+        ));
+    }
+    Ok(first.call::<String>("sendrawtransaction", &[json!(hex)])?)
+}
+
+// Sizes of a transaction computed locally from its serialization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeInfo {
+    // Bytes without the segwit marker, flag and witnesses
+    pub base_size: u64,
+    // Bytes as serialized, witnesses included
+    pub total_size: u64,
+    pub vsize: u64,
+    pub weight: u64,
+}
+
+// Decode a raw transaction and work out its sizes with the BIP141 formulas:
+// weight = 3 * base size + total size, and vsize = weight / 4 rounded up.
+pub fn size_breakdown(raw_hex: &str) -> Result<SizeInfo, AppError> {
+    let tx = decode_raw(raw_hex)?;
+    let base_size = tx.base_size() as u64;
+    let total_size = tx.total_size() as u64;
+    let weight = 3 * base_size + total_size;
+    // The library's own weight must agree, in release builds as much as in debug ones
+    let library_weight = tx.weight().to_wu();
+    if weight != library_weight {
+        return Err(AppError::Check(format!(
+            "computed weight {weight} WU differs from the decoded transaction's {library_weight} WU"
+        )));
+    }
+    Ok(SizeInfo {
+        base_size,
+        total_size,
+        vsize: weight.div_ceil(4),
+        weight,
+    })
+}
+
+fn decode_raw(raw_hex: &str) -> Result<Transaction, AppError> {
+    let bytes = Vec::<u8>::from_hex(raw_hex)
+        .map_err(|e| AppError::Check(format!("transaction hex is malformed: {e}")))?;
+    deserialize(&bytes).map_err(|e| AppError::Check(format!("transaction does not decode: {e}")))
+}
+
+// Recompute the txid from the raw bytes and check it is the one the node reported. The txid
+// is the double SHA-256 of the transaction serialized without witness data (shown byte-reversed),
+// so signatures can't change it. Segwit transactions also get a wtxid over the full
+// serialization, witnesses included; it is logged alongside.
+pub fn verify_txid(raw_hex: &str, expected: &str) -> Result<(), AppError> {
+    let tx = decode_raw(raw_hex)?;
+    let txid = tx.txid().to_string();
+    if txid != expected {
+        return Err(AppError::Check(format!(
+            "locally computed txid {txid} does not match the node's {expected}"
+        )));
+    }
+    println!("Local txid matches: {txid}");
+    if tx.input.iter().any(|input| !input.witness.is_empty()) {
+        println!("wtxid (includes witness data): {}", tx.wtxid());
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct FundedTx {
+    hex: String,
+}
+
+// Build and sign (but don't broadcast) a wallet transaction paying `amount` to `to`. The
+// wallet picks the inputs and adds change, as `sendtoaddress` would, sending the change to
+// `change_address` if one is given and paying `fee_rate` (sat/vB) if one is given.
+pub fn build_signed(
+    rpc: &Client,
+    to: &str,
+    amount: Amount,
+    change_address: Option<&str>,
+    fee_rate: Option<f64>,
+) -> Result<String, AppError> {
+    let raw = rpc.call::<String>(
+        "createrawtransaction",
+        &[json!([]), json!([{ to: amount.to_btc() }])],
+    )?;
+    let mut options = json!({});
+    if let Some(change_address) = change_address {
+        options["changeAddress"] = json!(change_address);
+    }
+    if let Some(fee_rate) = fee_rate {
+        options["fee_rate"] = json!(fee_rate);
+    }
+    let funded = rpc.call::<FundedTx>("fundrawtransaction", &[json!(raw), options])?;
+    let signed = rpc.call::<SignResult>("signrawtransactionwithwallet", &[json!(funded.hex)])?;
+    if !signed.complete {
+        return Err(AppError::Check(
+            "wallet could not sign the transaction".to_string(),
+        ));
+    }
+    Ok(signed.hex)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptFees {
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub base: Amount,
+}
+
+// One entry of the `testmempoolaccept` response. vsize and fees are only present when the
+// transaction would be accepted, reject_reason only when it wouldn't.
+#[derive(Debug, Deserialize)]
+pub struct AcceptResult {
+    pub txid: String,
+    pub allowed: bool,
+    pub vsize: Option<u64>,
+    pub fees: Option<AcceptFees>,
+    #[serde(rename = "reject-reason")]
+    pub reject_reason: Option<String>,
+}
+
+// Ask the node whether it would accept `raw_hex` into its mempool, without broadcasting it
+pub fn test_accept(rpc: &Client, raw_hex: &str) -> Result<AcceptResult, AppError> {
+    let mut results = rpc.call::<Vec<AcceptResult>>("testmempoolaccept", &[json!([raw_hex])])?;
+    results
+        .pop()
+        .ok_or_else(|| AppError::Check("testmempoolaccept returned no result".to_string()))
+}
+
+// Spend output `vout` of the still-unconfirmed `parent_txid` straight on to `to`, creating a
+// parent/child chain in the mempool. The wallet won't pick an unconfirmed incoming coin by
+// itself, so the input is given explicitly and a fixed-rate fee comes out of its value.
+pub fn chain_spend(
+    rpc: &Client,
+    parent_txid: &str,
+    vout: u32,
+    value: Amount,
+    to: &str,
+) -> Result<String, AppError> {
+    let fee = Amount::from_sat(estimate_vsize(1, 1) * RAW_FEE_RATE_SAT_VB);
+    let send = value
+        .checked_sub(fee)
+        .filter(|send| *send >= dust_limit("p2wpkh"))
+        .ok_or_else(|| {
+            AppError::Check(format!(
+                "{} BTC is too little to chain a spend",
+                value.to_btc()
+            ))
+        })?;
+    let hex = rpc.call::<String>(
+        "createrawtransaction",
+        &[
+            json!([{ "txid": parent_txid, "vout": vout }]),
+            json!([{ to: send.to_btc() }]),
+        ],
+    )?;
+    let signed = rpc.call::<SignResult>("signrawtransactionwithwallet", &[json!(hex)])?;
+    if !signed.complete {
+        return Err(AppError::Check(
+            "wallet could not sign the chained spend".to_string(),
+        ));
+    }
+    Ok(rpc.call::<String>("sendrawtransaction", &[json!(signed.hex)])?)
+}
+
+// The output an input spends, from wherever the node can find it: its mempool or txindex first
+// (`getrawtransaction`), then the wallet. None if neither knows the transaction.
+fn lookup_prevout(rpc: &Client, wallet: &Client, txid: &str, vout: usize) -> Option<Value> {
+    let tx = rpc
+        .call::<Value>("getrawtransaction", &[json!(txid), json!(true)])
+        .ok()
+        .or_else(|| {
+            let info = wallet
+                .call::<Value>("gettransaction", &[json!(txid), json!(null), json!(true)])
+                .ok()?;
+            decoded_tx(wallet, &info, txid).ok()
+        })?;
+    Some(tx["vout"][vout].clone()).filter(|out| !out.is_null())
+}
+
+// Where a transaction confirmed, as (block hash, height, confirmations), from the node's
+// txindex or else the wallet. None while it is unconfirmed or unknown to both.
+fn lookup_confirmation(rpc: &Client, wallet: &Client, txid: &str) -> Option<(String, i64, i64)> {
+    let info = rpc
+        .call::<Value>("getrawtransaction", &[json!(txid), json!(true)])
+        .ok()
+        .filter(|tx| tx["blockhash"].is_string())
+        .or_else(|| wallet.call::<Value>("gettransaction", &[json!(txid)]).ok())?;
+    let blockhash = info["blockhash"].as_str()?.to_string();
+    let header = rpc
+        .call::<Value>("getblockheader", &[json!(blockhash)])
+        .ok()?;
+    Some((
+        blockhash,
+        header["height"].as_i64()?,
+        header["confirmations"].as_i64()?,
+    ))
+}
+
+// Build the report for a raw transaction produced anywhere, not just by our wallets. Inputs are
+// looked up through the node and the Miner wallet; any that can't be found are reported as
+// "unknown", and the fee is only known when every input was found. Change is the first output
+// the Miner wallet owns and the payment the first other output with an address; a transaction
+// that already confirmed is reported with its block.
+pub fn report_from_raw(rpc: &Client, wallet: &Client, raw_hex: &str) -> Result<TxReport, AppError> {
+    let decoded = rpc.call::<Value>("decoderawtransaction", &[json!(raw_hex.trim())])?;
+    let empty = Vec::new();
+    let vin = decoded["vin"].as_array().unwrap_or(&empty);
+    let vout = decoded["vout"].as_array().unwrap_or(&empty);
+    let btc = |value: &Value| Amount::from_btc(value.as_f64().unwrap_or(0.0));
+
+    let mut prevouts = Vec::new();
+    for input in vin {
+        let txid = input["txid"].as_str().unwrap_or_default();
+        let vout = input["vout"].as_u64().unwrap_or_default() as usize;
+        let prevout = lookup_prevout(rpc, wallet, txid, vout);
+        if prevout.is_none() {
+            eprintln!("Warning: could not look up input {txid}:{vout}; reporting it as unknown");
+        }
+        prevouts.push(prevout);
+    }
+    let found = prevouts.iter().flatten().cloned().collect::<Vec<_>>();
+    let (input_total, outputs_total) = value_totals(&found, vout)?;
+    let all_resolved = !prevouts.is_empty() && prevouts.iter().all(Option::is_some);
+    let fee = if all_resolved {
+        input_total
+            .checked_sub(outputs_total)
+            .ok_or_else(|| AppError::Check("outputs exceed inputs".to_string()))?
+    } else {
+        Amount::ZERO
+    };
+
+    let first_input = prevouts.first().cloned().flatten();
+    let input_field = |field: &str| {
+        first_input
+            .as_ref()
+            .and_then(|out| out["scriptPubKey"][field].as_str())
+            .unwrap_or("unknown")
+            .to_string()
+    };
+    let owned = classify_outputs(wallet, vout)?;
+    let change_vout = owned.mine.first().copied();
+    let trader_vout = (0..vout.len())
+        .find(|&i| Some(i) != change_vout && vout[i]["scriptPubKey"]["address"].is_string());
+    let output_at = |index: Option<usize>| -> Result<(String, Amount), AppError> {
+        match index.and_then(|i| vout.get(i)) {
+            Some(out) => Ok((
+                out["scriptPubKey"]["address"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                btc(&out["value"])?,
+            )),
+            None => Ok((String::new(), Amount::ZERO)),
+        }
+    };
+    let (trader_output_address, trader_output_amount) = output_at(trader_vout)?;
+    let (miner_change_address, miner_change_amount) = output_at(change_vout)?;
+    let miner_input_amount = match &first_input {
+        Some(out) => btc(&out["value"])?,
+        None => Amount::ZERO,
+    };
+
+    let txid = decoded["txid"].as_str().unwrap_or_default().to_string();
+    let confirmation = lookup_confirmation(rpc, wallet, &txid);
+    let size = decoded["size"].as_u64().unwrap_or(0);
+    let vsize = decoded["vsize"].as_u64().unwrap_or(0);
+    let fee_units = fee_in_units(fee, vsize);
+    let (utxos_spent, utxos_created) = utxo_impact(vin, vout);
+    Ok(TxReport {
+        txid,
+        miner_input_address: input_field("address"),
+        miner_input_amount,
+        trader_output_address,
+        trader_output_amount,
+        miner_change_address,
+        miner_change_amount,
+        // The wallet's sign convention: negative for a payment
+        fee: SignedAmount::ZERO - fee.to_signed()?,
+        blockheight: confirmation.as_ref().map_or(0, |(_, height, _)| *height),
+        blockhash: confirmation
+            .as_ref()
+            .map_or("unconfirmed".to_string(), |(hash, _, _)| hash.clone()),
+        confirmed: confirmation.is_some(),
+        confirmations: confirmation.as_ref().map_or(0, |(_, _, depth)| *depth),
+        size,
+        vsize,
+        weight: decoded["weight"].as_u64().unwrap_or(0),
+        fee_btc: fee_units.btc,
+        fee_sats: fee_units.sats,
+        fee_rate: effective_fee_rate(fee, vsize),
+        package_fee_rate: effective_fee_rate(fee, vsize),
+        replaceable: signals_rbf(vin),
+        op_return_hex: op_return_data(vout),
+        fee_discrepancy: SignedAmount::ZERO,
+        is_segwit: is_segwit(vin),
+        witness_discount_vbytes: witness_discount_vbytes(size, vsize),
+        change_ratio: change_ratio(miner_change_amount, input_total),
+        coinbase_maturity_height: None,
+        input_depth_at_spend: None,
+        mining_address_hdkeypath: "none".to_string(),
+        trader_address_hdkeypath: "none".to_string(),
+        tx_version: decoded["version"].as_u64().unwrap_or(0) as u32,
+        tx_locktime: decoded["locktime"].as_u64().unwrap_or(0) as u32,
+        miner_input_type: input_field("type"),
+        total_input_value: input_total,
+        total_output_value: outputs_total,
+        utxos_spent,
+        utxos_created,
+        block_tx_index: None,
+        trader_vout,
+        change_vout,
+        confirmation_wall_secs: None,
+    })
+}
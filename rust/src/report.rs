@@ -0,0 +1,510 @@
+use crate::error::AppError;
+use bitcoincore_rpc::bitcoin::{Amount, Denomination, SignedAmount};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+// Everything we extract about the Miner -> Trader transaction. The first ten fields make up the
+// out.txt contract checked by the autograder; the rest are extra metrics we log alongside it
+// and include in the structured formats.
+// Amounts serialize as exact decimal BTC strings so no format loses precision to floats.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TxReport {
+    pub txid: String,
+    pub miner_input_address: String,
+    #[serde(with = "btc_string")]
+    pub miner_input_amount: Amount,
+    pub trader_output_address: String,
+    #[serde(with = "btc_string")]
+    pub trader_output_amount: Amount,
+    pub miner_change_address: String,
+    #[serde(with = "btc_string")]
+    pub miner_change_amount: Amount,
+    // As reported by the wallet, so it is negative for an outgoing transaction
+    #[serde(with = "signed_btc_string")]
+    pub fee: SignedAmount,
+    pub blockheight: i64,
+    pub blockhash: String,
+    // False with --no-confirm: the transaction is still in the mempool, blockheight is 0 and
+    // blockhash is "unconfirmed"
+    pub confirmed: bool,
+    // Confirmations the send had when the report was made
+    pub confirmations: i64,
+    // Serialized size in bytes, including witness data
+    pub size: u64,
+    pub vsize: u64,
+    pub weight: u64,
+    // The fee's magnitude in BTC and in satoshis
+    #[serde(with = "btc_string")]
+    pub fee_btc: Amount,
+    pub fee_sats: u64,
+    // Effective fee rate in sat/vB
+    pub fee_rate: f64,
+    // Fee rate of the transaction together with its unconfirmed descendants, in sat/vB
+    pub package_fee_rate: f64,
+    // Whether any input's sequence number signals BIP125 replaceability
+    pub replaceable: bool,
+    // Data embedded in an OP_RETURN output; absent on the standard two-output send
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub op_return_hex: Option<String>,
+    // Wallet fee minus (inputs - outputs); zero unless something was mis-parsed
+    #[serde(with = "signed_btc_string")]
+    pub fee_discrepancy: SignedAmount,
+    pub is_segwit: bool,
+    // vbytes saved by the witness discount (size - vsize)
+    pub witness_discount_vbytes: u64,
+    // Share of the input that came back as change (0 when there is no change output)
+    pub change_ratio: f64,
+    // When the first input spends a coinbase: the height it matured at (coinbase height + 100)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coinbase_maturity_height: Option<u64>,
+    // Confirmations the first input had when it was spent (spend height - input height)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_depth_at_spend: Option<u64>,
+    // HD derivation paths of the mining and Trader addresses ("none" if not HD-derived)
+    pub mining_address_hdkeypath: String,
+    pub trader_address_hdkeypath: String,
+    // Transaction version (2 enables BIP68 relative locktimes) and nLockTime
+    pub tx_version: u32,
+    pub tx_locktime: u32,
+    // scriptPubKey type of the spent output, e.g. "witness_v0_keyhash"
+    pub miner_input_type: String,
+    // Sum of every resolved input and every output; their difference is the fee
+    #[serde(with = "btc_string")]
+    pub total_input_value: Amount,
+    #[serde(with = "btc_string")]
+    pub total_output_value: Amount,
+    // Outputs removed from and added to the UTXO set (OP_RETURN outputs aren't added)
+    pub utxos_spent: u64,
+    pub utxos_created: u64,
+    // Position in the confirming block, the coinbase being 0; absent while unconfirmed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_tx_index: Option<usize>,
+    // Output indices of the payment and the change. Core puts change at a random position, so
+    // these vary from run to run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trader_vout: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change_vout: Option<usize>,
+    // Wall-clock seconds from the send until it reached the --confirmations depth (before any
+    // --mine-empty blocks). On regtest that is however long mining took; a real network
+    // averages ten minutes per block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmation_wall_secs: Option<f64>,
+}
+
+// How the report file is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    // The ten-line out.txt contract
+    Text,
+    Json,
+    Toml,
+    // KEY='value' lines that a shell can `source`
+    Env,
+    // A two-column markdown table, for pasting into issues and notes
+    Markdown,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "toml" => Ok(OutputFormat::Toml),
+            "env" => Ok(OutputFormat::Env),
+            "markdown" => Ok(OutputFormat::Markdown),
+            _ => Err(format!(
+                "unknown format {s} (expected text, json, toml, env or markdown)"
+            )),
+        }
+    }
+}
+
+// The decoded transaction as an indented tree: every input with the address and amount of the
+// output it spends (`resolved_inputs`, in vin order), then every output with its address,
+// amount and script type
+pub fn render_tx_tree(decoded: &Value, resolved_inputs: &[(String, Amount)]) -> String {
+    let empty = Vec::new();
+    let vin = decoded["vin"].as_array().unwrap_or(&empty);
+    let vout = decoded["vout"].as_array().unwrap_or(&empty);
+    let branch = |last: bool| if last { "└──" } else { "├──" };
+    let mut lines = vec![format!(
+        "tx {} (version {}, locktime {}, {} vbytes)",
+        decoded["txid"].as_str().unwrap_or("unknown"),
+        decoded["version"],
+        decoded["locktime"],
+        decoded["vsize"]
+    )];
+    lines.push(format!("├── inputs ({})", vin.len()));
+    for (i, input) in vin.iter().enumerate() {
+        let (address, amount) = resolved_inputs
+            .get(i)
+            .map(|(address, amount)| (address.as_str(), amount.to_btc().to_string()))
+            .unwrap_or(("unknown", "?".to_string()));
+        lines.push(format!(
+            "│   {} {i}: {}:{} from {address}, {amount} BTC",
+            branch(i + 1 == vin.len()),
+            input["txid"].as_str().unwrap_or("coinbase"),
+            input["vout"]
+        ));
+    }
+    lines.push(format!("└── outputs ({})", vout.len()));
+    for (i, output) in vout.iter().enumerate() {
+        let script = &output["scriptPubKey"];
+        lines.push(format!(
+            "    {} {i}: {}, {} BTC [{}]",
+            branch(i + 1 == vout.len()),
+            script["address"].as_str().unwrap_or("no address"),
+            output["value"].as_f64().unwrap_or(0.0),
+            script["type"].as_str().unwrap_or("unknown")
+        ));
+    }
+    lines.join("\n")
+}
+
+// Render the report in the given format, without a trailing newline
+pub fn render_report(report: &TxReport, format: OutputFormat) -> Result<String, AppError> {
+    match format {
+        OutputFormat::Text => Ok([
+            report.txid.clone(),
+            report.miner_input_address.clone(),
+            report.miner_input_amount.to_btc().to_string(),
+            report.trader_output_address.clone(),
+            report.trader_output_amount.to_btc().to_string(),
+            report.miner_change_address.clone(),
+            report.miner_change_amount.to_btc().to_string(),
+            report.fee.to_btc().to_string(),
+            if report.confirmed {
+                report.blockheight.to_string()
+            } else {
+                "unconfirmed".to_string()
+            },
+            report.blockhash.clone(),
+        ]
+        .join("\n")),
+        OutputFormat::Json => serde_json::to_string_pretty(report)
+            .map_err(|e| AppError::Check(format!("could not serialize report: {e}"))),
+        OutputFormat::Toml => {
+            let rendered = toml::to_string(report)
+                .map_err(|e| AppError::Check(format!("could not serialize report: {e}")))?;
+            // toml ends the document with a newline; the caller decides about that
+            Ok(rendered.trim_end().to_string())
+        }
+        OutputFormat::Env => {
+            // Same fields as the structured formats, via the serde representation
+            let value = serde_json::to_value(report)
+                .map_err(|e| AppError::Check(format!("could not serialize report: {e}")))?;
+            let fields = value.as_object().into_iter().flatten();
+            Ok(fields
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    // Single quotes keep the shell from interpreting anything; a ' inside is
+                    // closed, escaped and reopened
+                    let quoted = value.replace('\'', r"'\''");
+                    format!("{}='{quoted}'", key.to_uppercase())
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+        OutputFormat::Markdown => Ok(render_markdown(report)),
+    }
+}
+
+// The report as a markdown table with one labelled row per field. Amounts are always shown
+// with all 8 decimals so columns of them line up when compared across experiments.
+pub fn render_markdown(report: &TxReport) -> String {
+    let btc = |amount: Amount| format!("{:.8} BTC", amount.to_btc());
+    let signed_btc = |amount: SignedAmount| format!("{:.8} BTC", amount.to_btc());
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let rows = [
+        ("Transaction ID", report.txid.clone()),
+        ("Miner input address", report.miner_input_address.clone()),
+        ("Miner input amount", btc(report.miner_input_amount)),
+        ("Miner input type", report.miner_input_type.clone()),
+        (
+            "Trader output address",
+            report.trader_output_address.clone(),
+        ),
+        ("Trader output amount", btc(report.trader_output_amount)),
+        (
+            "Trader output index",
+            optional(report.trader_vout.map(|i| i.to_string())),
+        ),
+        ("Miner change address", report.miner_change_address.clone()),
+        ("Miner change amount", btc(report.miner_change_amount)),
+        (
+            "Miner change index",
+            optional(report.change_vout.map(|i| i.to_string())),
+        ),
+        ("Total input value", btc(report.total_input_value)),
+        ("Total output value", btc(report.total_output_value)),
+        ("Fee (wallet)", signed_btc(report.fee)),
+        ("Fee", btc(report.fee_btc)),
+        ("Fee in satoshis", report.fee_sats.to_string()),
+        ("Fee rate", format!("{:.2} sat/vB", report.fee_rate)),
+        (
+            "Package fee rate",
+            format!("{:.2} sat/vB", report.package_fee_rate),
+        ),
+        ("Fee discrepancy", signed_btc(report.fee_discrepancy)),
+        ("Confirmed", report.confirmed.to_string()),
+        ("Confirmations", report.confirmations.to_string()),
+        (
+            "Time to confirm",
+            optional(report.confirmation_wall_secs.map(|s| format!("{s:.3} s"))),
+        ),
+        ("Block height", report.blockheight.to_string()),
+        ("Block hash", report.blockhash.clone()),
+        (
+            "Position in block",
+            optional(report.block_tx_index.map(|i| i.to_string())),
+        ),
+        ("Size", format!("{} bytes", report.size)),
+        ("Virtual size", format!("{} vB", report.vsize)),
+        ("Weight", format!("{} WU", report.weight)),
+        ("SegWit", report.is_segwit.to_string()),
+        (
+            "Witness discount",
+            format!("{} vB", report.witness_discount_vbytes),
+        ),
+        ("Signals RBF", report.replaceable.to_string()),
+        ("OP_RETURN data", optional(report.op_return_hex.clone())),
+        ("Change ratio", format!("{:.4}", report.change_ratio)),
+        (
+            "Coinbase maturity height",
+            optional(report.coinbase_maturity_height.map(|h| h.to_string())),
+        ),
+        (
+            "Input depth at spend",
+            optional(report.input_depth_at_spend.map(|d| d.to_string())),
+        ),
+        (
+            "Mining address key path",
+            report.mining_address_hdkeypath.clone(),
+        ),
+        (
+            "Trader address key path",
+            report.trader_address_hdkeypath.clone(),
+        ),
+        ("Transaction version", report.tx_version.to_string()),
+        ("Locktime", report.tx_locktime.to_string()),
+        ("UTXOs spent", report.utxos_spent.to_string()),
+        ("UTXOs created", report.utxos_created.to_string()),
+    ];
+    let mut lines = vec!["| Field | Value |".to_string(), "| --- | --- |".to_string()];
+    for (label, value) in rows {
+        // A literal | would end the cell early
+        lines.push(format!("| {label} | `{}` |", value.replace('|', "\\|")));
+    }
+    lines.join("\n")
+}
+
+// Write the report to `path`. In text format that's the ten out.txt lines, one attribute per
+// line, amounts in BTC.
+// Missing parent directories are created first so a fresh checkout or custom --out path works.
+// With `trailing_newline` unset the last line is written without a newline, for strict checkers.
+pub fn write_report(
+    path: &Path,
+    report: &TxReport,
+    format: OutputFormat,
+    trailing_newline: bool,
+) -> Result<(), AppError> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("could not create directory {}: {e}", parent.display()),
+            )
+        })?;
+    }
+    let mut contents = render_report(report, format)?;
+    if trailing_newline {
+        contents.push('\n');
+    }
+    fs::write(path, contents).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("could not create {}: {e}", path.display()),
+        )
+    })?;
+    Ok(())
+}
+
+// Read a ten-line text report (out.txt) back into a TxReport. Only the ten contract fields are
+// in the file, so everything else is left at its default; `confirmed` follows from line 9.
+// Errors name the line that is missing or doesn't parse.
+pub fn parse_report_file(path: &Path) -> Result<TxReport, AppError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        std::io::Error::new(e.kind(), format!("could not read {}: {e}", path.display()))
+    })?;
+    let lines: Vec<&str> = text.lines().collect();
+    let line = |n: usize, field: &str| -> Result<String, AppError> {
+        lines.get(n - 1).map(|l| l.to_string()).ok_or_else(|| {
+            AppError::Check(format!("{} line {n} ({field}) is missing", path.display()))
+        })
+    };
+    let bad = |n: usize, field: &str, e: &dyn std::fmt::Display| {
+        AppError::Check(format!("{} line {n} ({field}): {e}", path.display()))
+    };
+    let amount = |n: usize, field: &str| -> Result<Amount, AppError> {
+        Amount::from_str_in(&line(n, field)?, Denomination::Bitcoin).map_err(|e| bad(n, field, &e))
+    };
+    if lines.len() > 10 {
+        return Err(AppError::Check(format!(
+            "{} has {} lines, expected 10",
+            path.display(),
+            lines.len()
+        )));
+    }
+    let fee = SignedAmount::from_str_in(&line(8, "fee")?, Denomination::Bitcoin)
+        .map_err(|e| bad(8, "fee", &e))?;
+    let height = line(9, "blockheight")?;
+    let confirmed = height != "unconfirmed";
+    let blockheight = if confirmed {
+        height.parse().map_err(|e| bad(9, "blockheight", &e))?
+    } else {
+        0
+    };
+    Ok(TxReport {
+        txid: line(1, "txid")?,
+        miner_input_address: line(2, "miner input address")?,
+        miner_input_amount: amount(3, "miner input amount")?,
+        trader_output_address: line(4, "trader output address")?,
+        trader_output_amount: amount(5, "trader output amount")?,
+        miner_change_address: line(6, "miner change address")?,
+        miner_change_amount: amount(7, "miner change amount")?,
+        fee,
+        blockheight,
+        blockhash: line(10, "blockhash")?,
+        confirmed,
+        ..Default::default()
+    })
+}
+
+// The part of a report that survives the text format: the ten contract fields and whether it
+// was confirmed, with everything else at its default
+pub fn text_contract(report: &TxReport) -> TxReport {
+    TxReport {
+        txid: report.txid.clone(),
+        miner_input_address: report.miner_input_address.clone(),
+        miner_input_amount: report.miner_input_amount,
+        trader_output_address: report.trader_output_address.clone(),
+        trader_output_amount: report.trader_output_amount,
+        miner_change_address: report.miner_change_address.clone(),
+        miner_change_amount: report.miner_change_amount,
+        fee: report.fee,
+        blockheight: if report.confirmed {
+            report.blockheight
+        } else {
+            0
+        },
+        blockhash: report.blockhash.clone(),
+        confirmed: report.confirmed,
+        ..Default::default()
+    }
+}
+
+// serde helpers writing amounts as exact decimal BTC strings, e.g. "29.9999859"
+mod btc_string {
+    use bitcoincore_rpc::bitcoin::{Amount, Denomination};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(amount: &Amount, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&amount.to_string_in(Denomination::Bitcoin))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Amount, D::Error> {
+        let raw = String::deserialize(d)?;
+        Amount::from_str_in(&raw, Denomination::Bitcoin).map_err(serde::de::Error::custom)
+    }
+}
+
+mod signed_btc_string {
+    use bitcoincore_rpc::bitcoin::{Denomination, SignedAmount};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(amount: &SignedAmount, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&amount.to_string_in(Denomination::Bitcoin))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<SignedAmount, D::Error> {
+        let raw = String::deserialize(d)?;
+        SignedAmount::from_str_in(&raw, Denomination::Bitcoin).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A confirmed 20 BTC send from a 50 BTC coinbase, paying 1410 sats at 10 sat/vB
+    fn sample_report() -> TxReport {
+        TxReport {
+            txid: "9f2c45a12eb9f3a7c8e6a2d1b0c4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0c1d2".to_string(),
+            miner_input_address: "bcrt1qgx5d3dyuqv2epdsuccsa4z2ag9ql2trlhfwzgn".to_string(),
+            miner_input_amount: Amount::from_int_btc(50),
+            trader_output_address: "bcrt1q6z3mxr5ljsqyp3t4u3rn0d6ah0kswhv0fune8a".to_string(),
+            trader_output_amount: Amount::from_int_btc(20),
+            miner_change_address: "bcrt1qmr7r5zgxw5egu0gqmm5lj5wlgx4fyhj5p2wcjy".to_string(),
+            miner_change_amount: Amount::from_sat(2_999_998_590),
+            fee: SignedAmount::from_sat(-1_410),
+            blockheight: 102,
+            blockhash: "3b1a0f7c6d5e4b3a291807f6e5d4c3b2a1908f7e6d5c4b3a2918f7e6d5c4b3a2"
+                .to_string(),
+            confirmed: true,
+            confirmations: 1,
+            size: 222,
+            vsize: 141,
+            weight: 561,
+            fee_btc: Amount::from_sat(1_410),
+            fee_sats: 1_410,
+            fee_rate: 10.0,
+            package_fee_rate: 10.0,
+            replaceable: true,
+            is_segwit: true,
+            witness_discount_vbytes: 81,
+            change_ratio: 0.5999997,
+            coinbase_maturity_height: Some(101),
+            input_depth_at_spend: Some(101),
+            mining_address_hdkeypath: "m/84h/1h/0h/0/0".to_string(),
+            trader_address_hdkeypath: "m/84h/1h/0h/0/0".to_string(),
+            tx_version: 2,
+            tx_locktime: 101,
+            miner_input_type: "witness_v0_keyhash".to_string(),
+            total_input_value: Amount::from_int_btc(50),
+            total_output_value: Amount::from_sat(4_999_998_590),
+            utxos_spent: 1,
+            utxos_created: 2,
+            block_tx_index: Some(1),
+            trader_vout: Some(0),
+            change_vout: Some(1),
+            confirmation_wall_secs: Some(0.25),
+            ..TxReport::default()
+        }
+    }
+
+    #[test]
+    fn toml_report_round_trips() {
+        let report = sample_report();
+        let rendered = render_report(&report, OutputFormat::Toml).unwrap();
+        let parsed: TxReport = toml::from_str(&rendered).unwrap();
+        assert_eq!(parsed, report);
+    }
+
+    #[test]
+    fn markdown_amounts_have_eight_decimals() {
+        let markdown = render_markdown(&sample_report());
+        assert!(markdown.contains("| Miner input amount | `50.00000000 BTC` |"));
+        assert!(markdown.contains("| Miner change amount | `29.99998590 BTC` |"));
+        assert!(markdown.contains("| Fee | `0.00001410 BTC` |"));
+        assert!(markdown.contains("| Fee discrepancy | `0.00000000 BTC` |"));
+    }
+}
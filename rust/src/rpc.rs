@@ -0,0 +1,390 @@
+use crate::error::AppError;
+use crate::proxy::Socks5Transport;
+use bitcoincore_rpc::{jsonrpc, Auth, Client, RpcApi};
+use serde::de::DeserializeOwned;
+use serde_json::value::to_raw_value;
+use serde_json::{json, Value};
+
+// Bitcoin Core release the node runs, e.g. 24.0.1. Fields in some RPC responses were renamed or
+// dropped between releases; the methods below pick the right one for this node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NodeVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl NodeVersion {
+    // `getnetworkinfo` encodes the version as MMmmpp, e.g. 240001 for 24.0.1
+    pub fn from_number(version: u64) -> Self {
+        NodeVersion {
+            major: version / 10_000,
+            minor: version / 100 % 100,
+            patch: version % 100,
+        }
+    }
+
+    // createwallet/loadwallet warnings: a `warnings` array from v25, a `warning` string before.
+    // Reading only the current one avoids double-reporting when the node returns both.
+    pub fn wallet_warnings_field(&self) -> &'static str {
+        if self.major >= 25 {
+            "warnings"
+        } else {
+            "warning"
+        }
+    }
+
+    // getmempoolentry's `bip125-replaceable` flag, gone from v26 on; after that replaceability
+    // can only be read from the inputs' sequence numbers
+    pub fn mempool_replaceable_field(&self) -> Option<&'static str> {
+        if self.major >= 26 {
+            None
+        } else {
+            Some("bip125-replaceable")
+        }
+    }
+}
+
+impl std::fmt::Display for NodeVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+// Read untyped: the typed getnetworkinfo result breaks on releases that changed other fields
+pub fn node_version(rpc: &Client) -> Result<NodeVersion, AppError> {
+    let info = rpc.call::<Value>("getnetworkinfo", &[])?;
+    let version = info["version"]
+        .as_u64()
+        .ok_or_else(|| AppError::Check("getnetworkinfo did not report a version".to_string()))?;
+    Ok(NodeVersion::from_number(version))
+}
+
+// How to reach the node: base RPC URL, credentials, and an optional SOCKS5 proxy
+pub struct NodeConfig {
+    pub url: String,
+    pub user: String,
+    pub pass: String,
+    pub proxy: Option<String>,
+}
+
+impl NodeConfig {
+    // Client for node-level RPCs
+    pub fn client(&self) -> Result<Client, AppError> {
+        self.connect(&self.url)
+    }
+
+    // Client whose wallet RPCs act on the named wallet
+    pub fn wallet_client(&self, wallet: &str) -> Result<Client, AppError> {
+        self.connect(&wallet_url(&self.url, wallet))
+    }
+
+    fn connect(&self, url: &str) -> Result<Client, AppError> {
+        match &self.proxy {
+            Some(proxy) => {
+                let transport = Socks5Transport::new(proxy, url, &self.user, &self.pass)?;
+                Ok(Client::from_jsonrpc(jsonrpc::Client::with_transport(
+                    transport,
+                )))
+            }
+            None => Ok(Client::new(
+                url,
+                Auth::UserPass(self.user.clone(), self.pass.clone()),
+            )?),
+        }
+    }
+}
+
+// URL of a wallet's RPC endpoint. The name is percent-encoded because it becomes a path
+// segment: "Test Wallet" or a path-like descriptor wallet name would otherwise break the URL.
+pub fn wallet_url(base: &str, wallet: &str) -> String {
+    let mut encoded = String::with_capacity(wallet.len());
+    for byte in wallet.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    format!("{}/wallet/{encoded}", base.trim_end_matches('/'))
+}
+
+// Parameter order of the RPCs we call by name, as listed in `bitcoin-cli help <rpc>`
+pub const SEND_PARAMS: &[&str] = &[
+    "outputs",
+    "conf_target",
+    "estimate_mode",
+    "fee_rate",
+    "options",
+];
+
+pub const SENDTOADDRESS_PARAMS: &[&str] = &[
+    "address",
+    "amount",
+    "comment",
+    "comment_to",
+    "subtractfeefromamount",
+    "replaceable",
+    "conf_target",
+    "estimate_mode",
+    "avoid_reuse",
+    "fee_rate",
+];
+
+// Call an RPC with named arguments. The JSON-RPC client only speaks positional params, so the
+// names are mapped onto `order` here: skipped parameters become nulls and trailing nulls are
+// dropped, which means nobody has to count placeholder nulls at the call site.
+pub fn call_named<T: DeserializeOwned>(
+    rpc: &Client,
+    method: &str,
+    order: &[&str],
+    named: Value,
+) -> Result<T, AppError> {
+    let Value::Object(mut named) = named else {
+        return Err(AppError::Usage(format!(
+            "named arguments for {method} must be an object"
+        )));
+    };
+    let mut args: Vec<Value> = order
+        .iter()
+        .map(|name| named.remove(*name).unwrap_or(Value::Null))
+        .collect();
+    if let Some(unknown) = named.keys().next() {
+        return Err(AppError::Usage(format!(
+            "{method} has no parameter named {unknown}"
+        )));
+    }
+    while args.last() == Some(&Value::Null) {
+        args.pop();
+    }
+    Ok(rpc.call::<T>(method, &args)?)
+}
+
+// RPCs the program relies on somewhere in its flow
+pub const REQUIRED_RPCS: &[&str] = &[
+    "createwallet",
+    "loadwallet",
+    "getnewaddress",
+    "generatetoaddress",
+    "listunspent",
+    "gettransaction",
+    "sendtoaddress",
+    "send",
+    "getrawmempool",
+    "getmempoolentry",
+    "walletcreatefundedpsbt",
+    "testmempoolaccept",
+    "gettxspendingprevout",
+    "getdescriptorinfo",
+    "importdescriptors",
+    "gettxoutsetinfo",
+];
+
+// Which of REQUIRED_RPCS the node knows about
+#[derive(Debug, Default)]
+pub struct RpcCapabilities {
+    pub available: Vec<&'static str>,
+    pub missing: Vec<&'static str>,
+}
+
+impl RpcCapabilities {
+    pub fn has(&self, method: &str) -> bool {
+        self.available.contains(&method)
+    }
+
+    // Fail up front when `feature` (a flag) needs an RPC this node lacks and has no fallback
+    pub fn require(&self, method: &str, feature: &str) -> Result<(), AppError> {
+        if self.has(method) {
+            return Ok(());
+        }
+        Err(AppError::Usage(format!(
+            "{feature} needs the {method} RPC, which this node doesn't have"
+        )))
+    }
+}
+
+// Ask `help <rpc>` for every RPC we use, so an older node is caught up front rather than
+// failing halfway through a run. `help` answers "help: unknown command: ..." for RPCs the node
+// doesn't have; a failing call also counts as unavailable.
+pub fn probe_capabilities(rpc: &Client) -> RpcCapabilities {
+    let mut capabilities = RpcCapabilities::default();
+    for &method in REQUIRED_RPCS {
+        let known = rpc
+            .call::<String>("help", &[json!(method)])
+            .map(|text| !text.starts_with("help: unknown command"))
+            .unwrap_or(false);
+        if known {
+            capabilities.available.push(method);
+        } else {
+            capabilities.missing.push(method);
+        }
+    }
+    capabilities
+}
+
+// `gettransaction` (with `decoded`) for every txid in a single JSON-RPC batch, so tracing a
+// transaction with many inputs costs one round-trip instead of one per input. If the batch
+// itself fails (e.g. a proxy or node that doesn't take batches) the txids are fetched one by
+// one instead; an error for an individual txid is returned either way.
+pub fn fetch_txs_batch(rpc: &Client, txids: &[String]) -> Result<Vec<Value>, AppError> {
+    if txids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let to_raw = |v: Value| to_raw_value(&v).map_err(|e| AppError::Check(e.to_string()));
+    let params = txids
+        .iter()
+        .map(|txid| {
+            Ok(vec![
+                to_raw(json!(txid))?,
+                to_raw(json!(null))?,
+                to_raw(json!(true))?,
+            ])
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+    let client = rpc.get_jsonrpc_client();
+    let requests = params
+        .iter()
+        .map(|params| client.build_request("gettransaction", params))
+        .collect::<Vec<_>>();
+    let responses = match client.send_batch(&requests) {
+        Ok(responses) => responses,
+        Err(e) => {
+            eprintln!("Warning: batch request failed ({e}), fetching transactions one by one");
+            return txids
+                .iter()
+                .map(|txid| {
+                    Ok(rpc.call::<Value>(
+                        "gettransaction",
+                        &[json!(txid), json!(null), json!(true)],
+                    )?)
+                })
+                .collect();
+        }
+    };
+    txids
+        .iter()
+        .zip(responses)
+        .map(|(txid, response)| {
+            let response = response
+                .ok_or_else(|| AppError::Check(format!("batch returned no response for {txid}")))?;
+            Ok(response
+                .result::<Value>()
+                .map_err(bitcoincore_rpc::Error::from)?)
+        })
+        .collect()
+}
+
+// What the node's most common send rejections mean, keyed by a fragment of the reject reason
+// or error message. Checked in order, so more specific fragments come first.
+const REJECT_EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "Insufficient funds",
+        "the wallet's spendable balance doesn't cover the amount plus fee; coinbase rewards \
+         only count once they have matured",
+    ),
+    (
+        "Fee estimation failed",
+        "the node has no fee estimate (normal on regtest) and no fallbackfee; pass \
+         --fallback-fee-rate or set -fallbackfee",
+    ),
+    (
+        "min relay fee not met",
+        "the fee rate is below the node's minimum relay fee (-minrelaytxfee)",
+    ),
+    (
+        "mempool min fee not met",
+        "the mempool is full and evicting transactions below a higher fee rate than this one",
+    ),
+    (
+        "insufficient fee",
+        "a replacement has to pay more in total and per vbyte than what it replaces (BIP125)",
+    ),
+    (
+        "Fee exceeds maximum",
+        "the fee is above the safety limit (-maxtxfee or maxfeerate); usually a units mix-up",
+    ),
+    (
+        "dust",
+        "an output is worth less than it would cost to spend, so the node won't relay it",
+    ),
+    (
+        "amount too small",
+        "the amount is too small to pay once the fee is taken out of it",
+    ),
+    (
+        "txn-mempool-conflict",
+        "another unconfirmed transaction already spends one of these inputs",
+    ),
+    (
+        "premature-spend-of-coinbase",
+        "an input is a coinbase output that hasn't reached maturity yet",
+    ),
+    (
+        "missingorspent",
+        "an input doesn't exist or has already been spent",
+    ),
+    (
+        "Missing inputs",
+        "an input doesn't exist or has already been spent",
+    ),
+    (
+        "non-final",
+        "the transaction's locktime is still in the future",
+    ),
+    (
+        "Invalid address",
+        "the destination isn't a valid address for this network",
+    ),
+];
+
+// Friendly explanation of a reject reason such as testmempoolaccept's `reject-reason`
+pub fn explain_reject_reason(reason: &str) -> Option<&'static str> {
+    REJECT_EXPLANATIONS
+        .iter()
+        .find(|(fragment, _)| reason.contains(fragment))
+        .map(|(_, explanation)| *explanation)
+}
+
+// Describe why a send RPC failed: the node's own message plus, for the common rejections, what
+// it means. Errors that aren't RPC rejections (e.g. the connection dropping) are shown as is.
+pub fn explain_send_failure(err: &bitcoincore_rpc::Error) -> String {
+    let bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(rpc_error)) = err else {
+        return err.to_string();
+    };
+    match explain_reject_reason(&rpc_error.message) {
+        Some(explanation) => format!(
+            "{} (code {}): {explanation}",
+            rpc_error.message, rpc_error.code
+        ),
+        None => format!("{} (code {})", rpc_error.message, rpc_error.code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mempool_replaceable_field_follows_the_release() {
+        assert_eq!(
+            NodeVersion::from_number(250100).mempool_replaceable_field(),
+            Some("bip125-replaceable")
+        );
+        assert_eq!(
+            NodeVersion::from_number(260000).mempool_replaceable_field(),
+            None
+        );
+    }
+
+    #[test]
+    fn wallet_url_percent_encodes_the_name() {
+        let url = wallet_url("http://127.0.0.1:18443/", "Test Wallet");
+        assert!(url.ends_with("/wallet/Test%20Wallet"), "{url}");
+        assert_eq!(url, "http://127.0.0.1:18443/wallet/Test%20Wallet");
+        assert_eq!(
+            wallet_url("http://127.0.0.1:18443", "a/b"),
+            "http://127.0.0.1:18443/wallet/a%2Fb"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::descriptor::import_descriptors;
+use crate::error::AppError;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// A canned regtest environment: each wallet's descriptors plus the chain height at export time.
+// With `redacted` set the descriptors only carry public keys.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Scenario {
+    pub height: u64,
+    pub redacted: bool,
+    pub wallets: BTreeMap<String, Vec<ScenarioDescriptor>>,
+}
+
+// One entry of `listdescriptors`, in the shape `importdescriptors` accepts back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioDescriptor {
+    pub desc: String,
+    pub timestamp: Value,
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ListDescriptors {
+    descriptors: Vec<ScenarioDescriptor>,
+}
+
+// Dump every wallet's descriptors and the current height to `path`.
+// These are real private keys, so unless `include_private_keys` is set only the public
+// descriptors are written and the file is marked as redacted.
+pub fn export_scenario(
+    rpc: &Client,
+    wallets: &[(&str, &Client)],
+    path: &Path,
+    include_private_keys: bool,
+) -> Result<(), AppError> {
+    let mut scenario = Scenario {
+        height: rpc.get_block_count()?,
+        redacted: !include_private_keys,
+        wallets: BTreeMap::new(),
+    };
+    for (name, wallet_rpc) in wallets {
+        let listed = wallet_rpc
+            .call::<ListDescriptors>("listdescriptors", &[json!(include_private_keys)])?;
+        scenario
+            .wallets
+            .insert(name.to_string(), listed.descriptors);
+    }
+    let json = serde_json::to_string_pretty(&scenario)
+        .map_err(|e| AppError::Scenario(format!("could not serialize scenario: {e}")))?;
+    fs::write(path, json)?;
+    if include_private_keys {
+        eprintln!(
+            "Warning: {} contains private keys; only share it for disposable regtest wallets",
+            path.display()
+        );
+    }
+    println!(
+        "Exported scenario at height {} to {}{}",
+        scenario.height,
+        path.display(),
+        if scenario.redacted {
+            " (keys redacted)"
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
+
+// Re-import the descriptors from a scenario file into the matching wallets
+pub fn import_scenario(
+    rpc: &Client,
+    wallets: &[(&str, &Client)],
+    path: &Path,
+) -> Result<(), AppError> {
+    let contents = fs::read_to_string(path)?;
+    let scenario: Scenario = serde_json::from_str(&contents)
+        .map_err(|e| AppError::Scenario(format!("could not parse {}: {e}", path.display())))?;
+    if scenario.redacted {
+        eprintln!(
+            "Warning: scenario keys are redacted; wallets with private keys enabled will reject its public descriptors"
+        );
+    }
+
+    for (name, wallet_rpc) in wallets {
+        let Some(descriptors) = scenario.wallets.get(*name) else {
+            eprintln!("Warning: scenario has no descriptors for wallet {name}");
+            continue;
+        };
+        let requests = descriptors
+            .iter()
+            .map(|d| {
+                // importdescriptors rejects explicit nulls, so only set the optional fields we have
+                let mut request = json!({
+                    "desc": d.desc,
+                    "timestamp": d.timestamp,
+                    "active": d.active,
+                });
+                if let Some(internal) = d.internal {
+                    request["internal"] = json!(internal);
+                }
+                if let Some(range) = &d.range {
+                    request["range"] = range.clone();
+                }
+                if let Some(next) = d.next {
+                    request["next_index"] = json!(next);
+                }
+                request
+            })
+            .collect::<Vec<_>>();
+        import_descriptors(wallet_rpc, requests)?;
+        println!(
+            "Imported {} descriptors into wallet {name}",
+            descriptors.len()
+        );
+    }
+
+    // The keys come back, but the blocks paying them only exist if the chain does too
+    let height = rpc.get_block_count()?;
+    if height < scenario.height {
+        eprintln!(
+            "Warning: chain height {height} is below the scenario's {}; its coins may be missing",
+            scenario.height
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Catch Ctrl-C (SIGINT) so a long-running mode can finish the step it's in and stop cleanly
+// instead of dying half-way through a send. The handler only sets a flag and puts the default
+// action back, so a second Ctrl-C still kills the process straight away.
+#[cfg(unix)]
+pub fn install_interrupt_handler() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        // signal() is async-signal-safe
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+    let handler: extern "C" fn(libc::c_int) = on_interrupt;
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+// Elsewhere Ctrl-C keeps its default behaviour
+#[cfg(not(unix))]
+pub fn install_interrupt_handler() {}
+
+// Has Ctrl-C been pressed since the handler was installed?
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,48 @@
+use crate::error::AppError;
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+
+// Writes the --status-file outcome when dropped, so the file appears on every exit path:
+// success, an error returned from the run, or a panic unwinding through main.
+#[derive(Debug, Default)]
+pub struct StatusGuard {
+    path: Option<PathBuf>,
+    // The send's txid, once there is one
+    pub txid: Option<String>,
+    // None until the run has finished one way or the other
+    outcome: Option<Result<(), String>>,
+}
+
+impl StatusGuard {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        StatusGuard {
+            path,
+            txid: None,
+            outcome: None,
+        }
+    }
+
+    pub fn record(&mut self, result: &Result<(), AppError>) {
+        self.outcome = Some(result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+    }
+}
+
+impl Drop for StatusGuard {
+    fn drop(&mut self) {
+        let Some(path) = &self.path else { return };
+        let (success, error) = match &self.outcome {
+            Some(Ok(())) => (true, None),
+            Some(Err(e)) => (false, Some(e.clone())),
+            None => (false, Some("run aborted before finishing".to_string())),
+        };
+        let status = json!({ "success": success, "error": error, "txid": self.txid });
+        // Nothing sensible is left to do with a failure here besides saying so
+        if let Err(e) = fs::write(path, format!("{status:#}\n")) {
+            eprintln!(
+                "Warning: could not write status file {}: {e}",
+                path.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+// Wall-clock time spent in each phase of the run. Starting a phase ends the previous one, so
+// the phases cover the run back to back.
+#[derive(Debug, Default)]
+pub struct PhaseTimer {
+    phases: Vec<(&'static str, Duration)>,
+    current: Option<(&'static str, Instant)>,
+}
+
+impl PhaseTimer {
+    pub fn start(&mut self, phase: &'static str) {
+        self.finish();
+        self.current = Some((phase, Instant::now()));
+    }
+
+    // End the running phase, if any
+    pub fn finish(&mut self) {
+        if let Some((phase, started)) = self.current.take() {
+            self.phases.push((phase, started.elapsed()));
+        }
+    }
+
+    // Time across all finished phases
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, d)| *d).sum()
+    }
+
+    // One line per phase with its duration and share of the total
+    pub fn breakdown(&self) -> String {
+        let total = self.total();
+        let mut lines = Vec::new();
+        for (phase, duration) in &self.phases {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                duration.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            lines.push(format!("  {phase:<14} {duration:>10.2?} ({share:.0}%)"));
+        }
+        lines.push(format!("  {:<14} {total:>10.2?}", "total"));
+        lines.join("\n")
+    }
+}
+
+// Bitcoin's target block interval. Mainnet averages about this; regtest mines on demand.
+pub const TARGET_BLOCK_INTERVAL: Duration = Duration::from_secs(600);
+
+// A confirmation measured both ways: `blocks` deep after `wall` of real time since the send,
+// next to what the same depth takes on a real network on average
+pub fn confirmation_summary(blocks: i64, wall: Duration) -> String {
+    let expected = TARGET_BLOCK_INTERVAL * blocks.max(0) as u32;
+    format!(
+        "Confirmed {blocks} block(s) deep in {:.3}s of wall-clock time; on mainnet that depth takes ~{} min",
+        wall.as_secs_f64(),
+        expected.as_secs() / 60
+    )
+}
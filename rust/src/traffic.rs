@@ -0,0 +1,100 @@
+use crate::error::AppError;
+use crate::fees::estimate_send_fee;
+use crate::mining::mine_blocks;
+use crate::signal::{install_interrupt_handler, interrupted};
+use crate::wallet::{new_address, preflight_balance};
+use crate::{perform_send, SendMethod};
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// How often the pause between iterations checks for Ctrl-C
+const INTERRUPT_CHECK: Duration = Duration::from_millis(100);
+
+// Settings for --loop
+pub struct LoopOptions<'a> {
+    pub interval: Duration,
+    pub amount: Amount,
+    pub network: Network,
+    pub send_method: SendMethod,
+    pub fee_rate: Option<f64>,
+    pub max_tries: Option<u64>,
+    // Each iteration appends one JSON line here
+    pub log: &'a Path,
+}
+
+// Keep making traffic until Ctrl-C: every `interval`, pay `amount` from the Miner to a fresh
+// Trader address, confirm it with one block and append the outcome to the log. Ctrl-C is
+// honoured between steps, so an iteration always finishes. Returns the iterations completed.
+pub fn send_loop(
+    rpc: &Client,
+    miner_rpc: &Client,
+    trader_rpc: &Client,
+    mining_address: &Address,
+    options: &LoopOptions,
+) -> Result<u64, AppError> {
+    install_interrupt_handler();
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(options.log)
+        .map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("could not open {}: {e}", options.log.display()),
+            )
+        })?;
+    println!(
+        "Sending {} BTC every {:?} until Ctrl-C; logging to {}",
+        options.amount.to_btc(),
+        options.interval,
+        options.log.display()
+    );
+    let mut completed = 0;
+    while !interrupted() {
+        let address = new_address(trader_rpc, "Loop", options.network)?;
+        preflight_balance(
+            miner_rpc,
+            options.amount,
+            estimate_send_fee(options.fee_rate),
+        )?;
+        let txid = perform_send(
+            miner_rpc,
+            options.send_method,
+            &address.to_string(),
+            options.amount,
+            false,
+            options.fee_rate,
+        )?;
+        mine_blocks(rpc, 1, mining_address, false, options.max_tries)?;
+        let tx = miner_rpc.call::<serde_json::Value>("gettransaction", &[json!(txid)])?;
+        completed += 1;
+        let entry = json!({
+            "iteration": completed,
+            "txid": txid,
+            "address": address,
+            "amount": options.amount.to_btc(),
+            "fee": tx["fee"],
+            "blockheight": tx["blockheight"],
+            "blockhash": tx["blockhash"],
+        });
+        writeln!(log, "{entry}")?;
+        println!(
+            "Iteration {completed}: {txid} confirmed at height {}",
+            tx["blockheight"]
+        );
+
+        // Wait out the interval in small steps so Ctrl-C doesn't have to wait for it
+        let mut waited = Duration::ZERO;
+        while waited < options.interval && !interrupted() {
+            thread::sleep(INTERRUPT_CHECK);
+            waited += INTERRUPT_CHECK;
+        }
+    }
+    Ok(completed)
+}
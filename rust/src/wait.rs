@@ -0,0 +1,43 @@
+use crate::error::AppError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often and how long the program's wait loops poll the node
+#[derive(Debug, Clone, Copy)]
+pub struct WaitConfig {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for WaitConfig {
+    fn default() -> Self {
+        WaitConfig {
+            interval: Duration::from_millis(200),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl WaitConfig {
+    // Call `check` every `interval` until it yields a value, or fail once `timeout` has passed.
+    // `what` describes the awaited condition for the timeout error.
+    pub fn poll<T>(
+        &self,
+        what: &str,
+        mut check: impl FnMut() -> Result<Option<T>, AppError>,
+    ) -> Result<T, AppError> {
+        let start = Instant::now();
+        loop {
+            if let Some(value) = check()? {
+                return Ok(value);
+            }
+            if start.elapsed() >= self.timeout {
+                return Err(AppError::Timeout(format!(
+                    "{what} (gave up after {:?})",
+                    self.timeout
+                )));
+            }
+            thread::sleep(self.interval);
+        }
+    }
+}
@@ -0,0 +1,554 @@
+use crate::error::AppError;
+use crate::rpc::{call_named, NodeVersion, SEND_PARAMS};
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+// Names of the wallets the node currently has loaded
+pub fn list_wallets(rpc: &Client) -> Result<Vec<String>, AppError> {
+    Ok(rpc.call::<Vec<String>>("listwallets", &[])?)
+}
+
+// Create the named wallet, or load it if it already exists, and surface any warnings the node
+// attaches to the response (e.g. deprecation notices) instead of dropping them.
+pub fn ensure_wallet(rpc: &Client, wallet: &str, version: &NodeVersion) -> Result<(), AppError> {
+    create_or_load(rpc, wallet, &[json!(wallet)], version)
+}
+
+// ensure_wallet for a wallet with private keys disabled. A descriptor wallet that holds private
+// keys refuses watch-only imports, so watched addresses need one of these.
+pub fn ensure_watch_only_wallet(
+    rpc: &Client,
+    wallet: &str,
+    version: &NodeVersion,
+) -> Result<(), AppError> {
+    create_or_load(rpc, wallet, &[json!(wallet), json!(true)], version)
+}
+
+fn create_or_load(
+    rpc: &Client,
+    wallet: &str,
+    create_params: &[Value],
+    version: &NodeVersion,
+) -> Result<(), AppError> {
+    let response = match rpc.call::<Value>("createwallet", create_params) {
+        Ok(response) => response,
+        Err(e) if e.to_string().contains("already exists") => {
+            // If the wallet already exists, attempt to load it in case it's not currently loaded
+            match rpc.call::<Value>("loadwallet", &[json!(wallet)]) {
+                Ok(response) => response,
+                Err(_) => return Ok(()), // already loaded
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+    for warning in response_warnings(&response, version.wallet_warnings_field()) {
+        eprintln!("Warning from node for wallet {wallet}: {warning}");
+    }
+    Ok(())
+}
+
+// Older nodes return a single `warning` string (empty when there is none), newer ones a
+// `warnings` array; `field` says which one this node uses
+fn response_warnings(response: &Value, field: &str) -> Vec<String> {
+    match &response[field] {
+        Value::Array(list) => list
+            .iter()
+            .filter_map(|w| w.as_str().map(str::to_string))
+            .collect(),
+        Value::String(warning) if !warning.is_empty() => vec![warning.clone()],
+        _ => Vec::new(),
+    }
+}
+
+// Label prefix of the marker address we stamp into each wallet in --strict-wallet mode
+const MARKER_PREFIX: &str = "capstone-marker:";
+
+// Check that a loaded wallet looks like it belongs to this node and network.
+// Wallets are looked up by name only, so a "Miner" wallet left behind by another regtest instance
+// or another network is picked up silently. We stamp a marker label recording the context the
+// wallet was first used in, warn when it disagrees, and check existing addresses are for `network`.
+pub fn check_wallet_context(
+    rpc: &Client,
+    wallet: &str,
+    network: Network,
+    rpc_url: &str,
+) -> Result<(), AppError> {
+    let expected = format!("{MARKER_PREFIX}{network}@{rpc_url}");
+    let labels = rpc.call::<Vec<String>>("listlabels", &[])?;
+
+    match labels.iter().find(|l| l.starts_with(MARKER_PREFIX)) {
+        Some(marker) if *marker != expected => eprintln!(
+            "Warning: wallet {wallet} appears to be from a different context ({}, expected {})",
+            marker.trim_start_matches(MARKER_PREFIX),
+            expected.trim_start_matches(MARKER_PREFIX)
+        ),
+        Some(_) => println!("Wallet {wallet} context verified"),
+        None => {
+            // First run with this wallet: record where it is being used
+            rpc.call::<String>("getnewaddress", &[json!(expected)])?;
+            println!("Stamped wallet {wallet} with context marker");
+        }
+    }
+
+    // Any address the wallet already handed out must belong to the expected network
+    for label in labels.iter().filter(|l| !l.starts_with(MARKER_PREFIX)) {
+        let addresses =
+            rpc.call::<HashMap<String, Value>>("getaddressesbylabel", &[json!(label)])?;
+        if let Some(first) = addresses.keys().next() {
+            if let Err(e) = parse_address(first, network) {
+                eprintln!("Warning: wallet {wallet} holds an unexpected address: {e}");
+            }
+        }
+    }
+    Ok(())
+}
+
+// Make sure the wallet has pregenerated keys left for getnewaddress to hand out. An exhausted
+// keypool (e.g. after -keypool=0 or a lot of address generation) is refilled with
+// `keypoolrefill`; a wallet without private keys can't derive more, which is only a warning.
+pub fn ensure_keypool(rpc: &Client, wallet: &str) -> Result<(), AppError> {
+    let keypool_size = || -> Result<u64, AppError> {
+        let info = rpc.call::<Value>("getwalletinfo", &[])?;
+        Ok(info["keypoolsize"].as_u64().unwrap_or(0))
+    };
+    let before = keypool_size()?;
+    if before > 0 {
+        println!("Wallet {wallet} keypool: {before} keys");
+        return Ok(());
+    }
+    if let Err(e) = rpc.call::<Value>("keypoolrefill", &[]) {
+        eprintln!("Warning: wallet {wallet} keypool is empty and could not be refilled: {e}");
+        return Ok(());
+    }
+    println!(
+        "Wallet {wallet} keypool was empty; refilled to {} keys",
+        keypool_size()?
+    );
+    Ok(())
+}
+
+// Ask the wallet for a fresh address and validate it for `network` before handing it out
+pub fn new_address(rpc: &Client, label: &str, network: Network) -> Result<Address, AppError> {
+    let address = rpc.call::<String>("getnewaddress", &[json!(label)])?;
+    parse_address(&address, network)
+}
+
+// Parse an address string, rejecting it if it belongs to a different network. Every address
+// the program takes from the node or the user goes through here: it is parsed unchecked first
+// and only then bound to `network`, so the error says which network was expected.
+pub fn parse_address(address: &str, network: Network) -> Result<Address, AppError> {
+    Address::from_str(address)
+        .map_err(|e| AppError::Address(format!("could not parse {address}: {e}")))?
+        .require_network(network)
+        .map_err(|_| AppError::Address(format!("{address} is not a valid {network} address")))
+}
+
+// Does this wallet hold the keys for `address`?
+pub fn is_mine(rpc: &Client, address: &str) -> Result<bool, AppError> {
+    let info = rpc.call::<Value>("getaddressinfo", &[json!(address)])?;
+    Ok(info["ismine"].as_bool().unwrap_or(false))
+}
+
+// Whether the wallet was created with `avoid_reuse`: it then keeps coins on already-used
+// addresses out of coin selection, which changes which inputs (and so what change) a send gets
+pub fn avoid_reuse(rpc: &Client) -> Result<bool, AppError> {
+    let info = rpc.call::<Value>("getwalletinfo", &[])?;
+    Ok(info["avoid_reuse"].as_bool().unwrap_or(false))
+}
+
+// Recipient-side check: the Trader wallet lists an output of `txid` worth `amount` as unspent
+// and spendable. An output that is still unconfirmed is reported as pending rather than failed.
+pub fn assert_trader_utxo(rpc: &Client, txid: &str, amount: Amount) -> Result<(), AppError> {
+    // minconf 0 so an unconfirmed output shows up too
+    let unspent = rpc.call::<Vec<Value>>("listunspent", &[json!(0)])?;
+    let utxo = unspent
+        .iter()
+        .find(|u| u["txid"].as_str() == Some(txid))
+        .ok_or_else(|| {
+            AppError::Check(format!("Trader wallet has no unspent output from {txid}"))
+        })?;
+    let value = Amount::from_btc(utxo["amount"].as_f64().unwrap_or(0.0))?;
+    if value != amount {
+        return Err(AppError::Check(format!(
+            "Trader received {} BTC from {txid}, expected {} BTC",
+            value.to_btc(),
+            amount.to_btc()
+        )));
+    }
+    if !utxo["spendable"].as_bool().unwrap_or(false) {
+        return Err(AppError::Check(format!(
+            "Trader's output from {txid} is not spendable"
+        )));
+    }
+    match utxo["confirmations"].as_u64().unwrap_or(0) {
+        0 => println!(
+            "Trader's {} BTC from {txid} is pending: it needs a confirmation",
+            amount.to_btc()
+        ),
+        n => println!(
+            "Trader can spend its {} BTC from {txid} ({n} confirmations)",
+            amount.to_btc()
+        ),
+    }
+    Ok(())
+}
+
+// Balance of a watch-only wallet and whether any of it is spendable. Without private keys the
+// wallet can see its coins (listunspent reports them solvable) but never sign for them.
+pub fn watch_only_balance(rpc: &Client) -> Result<(Amount, bool), AppError> {
+    let unspent = rpc.call::<Vec<Value>>("listunspent", &[])?;
+    let mut total = Amount::ZERO;
+    for utxo in &unspent {
+        total += Amount::from_btc(utxo["amount"].as_f64().unwrap_or(0.0))?;
+    }
+    let spendable = unspent
+        .iter()
+        .any(|u| u["spendable"].as_bool() == Some(true));
+    Ok((total, spendable))
+}
+
+// Check the wallet can cover `amount` plus `fee_estimate` from its trusted (confirmed or own
+// unconfirmed) balance before any send is attempted, so an underfunded run fails with the
+// shortfall spelled out instead of the node's bare "Insufficient funds" (-4)
+pub fn preflight_balance(
+    rpc: &Client,
+    amount: Amount,
+    fee_estimate: Amount,
+) -> Result<(), AppError> {
+    let balances = rpc.call::<Value>("getbalances", &[])?;
+    let spendable = Amount::from_btc(balances["mine"]["trusted"].as_f64().unwrap_or(0.0))?;
+    let needed = amount + fee_estimate;
+    if needed > spendable {
+        return Err(AppError::Check(format!(
+            "sending {} BTC plus about {} BTC in fees needs {} BTC, but only {} BTC is spendable \
+             ({} BTC short)",
+            amount.to_btc(),
+            fee_estimate.to_btc(),
+            needed.to_btc(),
+            spendable.to_btc(),
+            (needed - spendable).to_btc()
+        )));
+    }
+    Ok(())
+}
+
+// Number of transactions the wallet knows about
+pub fn wallet_tx_count(rpc: &Client) -> Result<usize, AppError> {
+    let info = rpc.call::<Value>("getwalletinfo", &[])?;
+    Ok(info["txcount"].as_u64().unwrap_or(0) as usize)
+}
+
+// Total fees the wallet has paid over its lifetime: the `fee` of every send in
+// `listtransactions`. Receives carry no fee and are skipped. A transaction paying several
+// outputs has one send entry per output, each repeating the fee, so it is counted once per txid.
+pub fn lifetime_fees(rpc: &Client) -> Result<Amount, AppError> {
+    // listtransactions returns a page at a time; keep skipping ahead until a short page
+    const PAGE: usize = 500;
+    let mut entries = Vec::new();
+    loop {
+        let page = rpc.call::<Vec<Value>>(
+            "listtransactions",
+            &[json!("*"), json!(PAGE), json!(entries.len())],
+        )?;
+        let done = page.len() < PAGE;
+        entries.extend(page);
+        if done {
+            break;
+        }
+    }
+    let mut seen = HashSet::new();
+    let mut total = Amount::ZERO;
+    for entry in &entries {
+        let (Some(txid), Some(fee)) = (entry["txid"].as_str(), entry["fee"].as_f64()) else {
+            continue;
+        };
+        if entry["category"].as_str() != Some("send") || !seen.insert(txid) {
+            continue;
+        }
+        total += Amount::from_btc(fee.abs())?;
+    }
+    Ok(total)
+}
+
+// Check the wallet gained exactly one transaction since `before_count` was taken, so a retry
+// can't have quietly sent the payment twice. Logs the new transaction's txid.
+pub fn assert_single_new_tx(rpc: &Client, before_count: usize) -> Result<(), AppError> {
+    let after_count = wallet_tx_count(rpc)?;
+    if after_count != before_count + 1 {
+        return Err(AppError::Check(format!(
+            "expected exactly one new wallet transaction, found {}",
+            after_count as i64 - before_count as i64
+        )));
+    }
+    // listtransactions is oldest first, so the newest entry is last
+    let recent = rpc.call::<Vec<Value>>("listtransactions", &[json!("*"), json!(1)])?;
+    let txid = recent
+        .last()
+        .and_then(|tx| tx["txid"].as_str())
+        .unwrap_or("unknown");
+    println!("Send created one new wallet transaction: {txid}");
+    Ok(())
+}
+
+// Output indices of a transaction split by whether the wallet owns them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputClassification {
+    // Outputs to the wallet's own addresses (change, for a send)
+    pub mine: Vec<usize>,
+    // Everything else: the payment and any outputs without an address
+    pub external: Vec<usize>,
+}
+
+// Ask the wallet (`getaddressinfo` ismine) about every output instead of guessing from
+// address strings, so change detection holds whatever addresses are involved
+pub fn classify_outputs(rpc: &Client, vout: &[Value]) -> Result<OutputClassification, AppError> {
+    let mut classification = OutputClassification::default();
+    for (i, out) in vout.iter().enumerate() {
+        let owned = match out["scriptPubKey"]["address"].as_str() {
+            Some(address) => is_mine(rpc, address)?,
+            None => false,
+        };
+        if owned {
+            classification.mine.push(i);
+        } else {
+            classification.external.push(i);
+        }
+    }
+    Ok(classification)
+}
+
+// BIP32 derivation path of one of the wallet's addresses, e.g. "m/84h/1h/0h/0/0", or "none"
+// for addresses that weren't derived from an HD key (such as imported single keys)
+pub fn hd_key_path(rpc: &Client, address: &str) -> Result<String, AppError> {
+    let info = rpc.call::<Value>("getaddressinfo", &[json!(address)])?;
+    Ok(info["hdkeypath"].as_str().unwrap_or("none").to_string())
+}
+
+// Pay `amount` to `address` with `send`, forcing the change to `change_address`.
+// `sendtoaddress` always picks its own change address, so this is the path for explicit change.
+pub fn send_with_change(
+    rpc: &Client,
+    address: &str,
+    amount: Amount,
+    change_address: &str,
+    replaceable: bool,
+    fee_rate: Option<f64>,
+) -> Result<String, AppError> {
+    #[derive(Deserialize)]
+    struct SendResult {
+        complete: bool,
+        txid: Option<String>,
+    }
+    let mut options = json!({ "change_address": change_address });
+    if replaceable {
+        options["replaceable"] = json!(true);
+    }
+    let result = call_named::<SendResult>(
+        rpc,
+        "send",
+        SEND_PARAMS,
+        json!({
+            "outputs": [{ address: amount.to_btc() }],
+            "fee_rate": fee_rate,
+            "options": options,
+        }),
+    )?;
+    match (result.complete, result.txid) {
+        (true, Some(txid)) => Ok(txid),
+        _ => Err(AppError::Check(
+            "send did not complete the transaction".to_string(),
+        )),
+    }
+}
+
+// Has this wallet already received coins at `address`? Reusing an address links payments
+// together on-chain, which is exactly what fresh addresses are meant to prevent. Read-only:
+// a reused address is reported, not refused.
+pub fn check_address_reuse(rpc: &Client, address: &str) -> Result<bool, AppError> {
+    // minconf 0 so unconfirmed payments to the address count too
+    let received = rpc.call::<f64>("getreceivedbyaddress", &[json!(address), json!(0)])?;
+    let reused = received > 0.0;
+    if reused {
+        eprintln!(
+            "Privacy warning: {address} has already received {received} BTC; reusing it links these payments together"
+        );
+    }
+    Ok(reused)
+}
+
+// One entry of `listunspent`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Utxo {
+    pub txid: String,
+    pub vout: u32,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub amount: Amount,
+    pub confirmations: u32,
+}
+
+pub fn list_unspent(rpc: &Client) -> Result<Vec<Utxo>, AppError> {
+    Ok(rpc.call::<Vec<Utxo>>("listunspent", &[])?)
+}
+
+// Coins reserved with `lockunspent` so coin selection skips them. They are unlocked again when
+// the lock is released or dropped, so an error part-way through the run doesn't leave the
+// wallet with coins it won't spend.
+pub struct UtxoLock<'a> {
+    rpc: &'a Client,
+    outpoints: Vec<(String, u32)>,
+}
+
+impl<'a> UtxoLock<'a> {
+    // Lock the given outpoints, each of which must be one of the wallet's unspent outputs
+    pub fn lock(rpc: &'a Client, outpoints: &[(String, u32)]) -> Result<Self, AppError> {
+        let unspent = list_unspent(rpc)?;
+        for (txid, vout) in outpoints {
+            if !unspent.iter().any(|u| u.txid == *txid && u.vout == *vout) {
+                return Err(AppError::Check(format!(
+                    "{txid}:{vout} is not an unspent output of this wallet"
+                )));
+            }
+        }
+        if !outpoints.is_empty() {
+            rpc.call::<bool>("lockunspent", &[json!(false), outpoint_list(outpoints)])?;
+        }
+        Ok(UtxoLock {
+            rpc,
+            outpoints: outpoints.to_vec(),
+        })
+    }
+
+    // Unlock now rather than at the end of scope
+    pub fn release(mut self) -> Result<(), AppError> {
+        self.unlock()
+    }
+
+    fn unlock(&mut self) -> Result<(), AppError> {
+        if self.outpoints.is_empty() {
+            return Ok(());
+        }
+        let outpoints = std::mem::take(&mut self.outpoints);
+        self.rpc
+            .call::<bool>("lockunspent", &[json!(true), outpoint_list(&outpoints)])?;
+        println!("Unlocked {} coin(s)", outpoints.len());
+        Ok(())
+    }
+}
+
+impl Drop for UtxoLock<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.unlock() {
+            eprintln!("Warning: could not unlock coins: {e}");
+        }
+    }
+}
+
+fn outpoint_list(outpoints: &[(String, u32)]) -> Value {
+    json!(outpoints
+        .iter()
+        .map(|(txid, vout)| json!({ "txid": txid, "vout": vout }))
+        .collect::<Vec<_>>())
+}
+
+// Which of the wallet's UTXOs (snapshotted before the send) did coin selection pick?
+// Matches the decoded transaction's vin prevouts against the snapshot.
+pub fn consumed_utxos(before: &[Utxo], vin: &[Value]) -> Vec<Utxo> {
+    vin.iter()
+        .filter_map(|input| {
+            let txid = input["txid"].as_str()?;
+            let vout = input["vout"].as_u64()?;
+            before
+                .iter()
+                .find(|u| u.txid == txid && u64::from(u.vout) == vout)
+                .cloned()
+        })
+        .collect()
+}
+
+// The `decoded` transaction from a `gettransaction` result. Only filled in when the call asked
+// for verbose output, so if it is missing or null, ask the node for the transaction with
+// `getrawtransaction` (verbose) instead, passing the block hash so no txindex is needed.
+pub fn decoded_tx(rpc: &Client, tx_info: &Value, txid: &str) -> Result<Value, AppError> {
+    if !tx_info["decoded"].is_null() {
+        return Ok(tx_info["decoded"].clone());
+    }
+    let mut params = vec![json!(txid), json!(true)];
+    if let Some(blockhash) = tx_info["blockhash"].as_str() {
+        params.push(json!(blockhash));
+    }
+    match rpc.call::<Value>("getrawtransaction", &params) {
+        Ok(decoded) if decoded["vout"].is_array() => Ok(decoded),
+        Ok(_) => Err(AppError::Check(format!(
+            "no decoded form of transaction {txid} available"
+        ))),
+        Err(e) => Err(AppError::Check(format!(
+            "gettransaction returned no decoded transaction for {txid} and \
+             getrawtransaction failed too: {e}"
+        ))),
+    }
+}
+
+// The output `txid:vout` that one of the wallet's inputs spends, as decoded by `gettransaction`
+pub fn fetch_prevout(rpc: &Client, txid: &str, vout: usize) -> Result<Value, AppError> {
+    let tx = rpc.call::<Value>("gettransaction", &[json!(txid), json!(null), json!(true)])?;
+    Ok(decoded_tx(rpc, &tx, txid)?["vout"][vout].clone())
+}
+
+// fetch_prevout for input tracing that tolerates pruned nodes: if the lookup fails because the
+// node no longer has the block data, warn and fall back to what the pre-send UTXO snapshot
+// knows (the value), leaving the address unknown.
+pub fn trace_prevout(
+    rpc: &Client,
+    txid: &str,
+    vout: usize,
+    pruned: bool,
+    snapshot: &[Utxo],
+) -> Result<Value, AppError> {
+    match fetch_prevout(rpc, txid, vout) {
+        Ok(prevout) => Ok(prevout),
+        Err(AppError::Rpc(e)) if pruned && is_pruned_data_error(&e.to_string()) => {
+            eprintln!("Warning: can't trace input {txid}:{vout} on this pruned node: {e}");
+            let value = snapshot
+                .iter()
+                .find(|u| u.txid == txid && u.vout as usize == vout)
+                .map(|u| u.amount.to_btc());
+            Ok(json!({ "value": value, "scriptPubKey": {} }))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn is_pruned_data_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("not available") || message.contains("pruned")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGTEST_ADDRESS: &str = "bcrt1q7f0pjwhc3jzzv0w4uurm589506glv2dggfan4s";
+    const TESTNET_ADDRESS: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+    #[test]
+    fn parse_address_accepts_each_network_its_own_addresses() {
+        let address = parse_address(REGTEST_ADDRESS, Network::Regtest).unwrap();
+        assert_eq!(address.to_string(), REGTEST_ADDRESS);
+        let address = parse_address(TESTNET_ADDRESS, Network::Testnet).unwrap();
+        assert_eq!(address.to_string(), TESTNET_ADDRESS);
+    }
+
+    #[test]
+    fn parse_address_rejects_a_testnet_address_on_regtest() {
+        let Err(AppError::Address(message)) = parse_address(TESTNET_ADDRESS, Network::Regtest)
+        else {
+            panic!("a testnet address was accepted on regtest");
+        };
+        assert!(message.contains(TESTNET_ADDRESS), "{message}");
+        assert!(message.contains("regtest"), "{message}");
+    }
+}
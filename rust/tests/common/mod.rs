@@ -0,0 +1,103 @@
+// Shared setup for the integration tests: finding a regtest node, running the whole program
+// against it, and comparing its report with a golden copy. Each test file uses a different
+// subset, hence the allow.
+#![allow(dead_code)]
+
+use bitcoincore_rpc::bitcoin::{Denomination, SignedAmount};
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// The seed from the README's reproducible-addresses example, which the golden report is for
+pub const SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+// Same variables and defaults the program itself uses, so both talk to the same node
+fn setting(var: &str, default: &str) -> String {
+    env::var(var).unwrap_or_else(|_| default.to_string())
+}
+
+// A client for the regtest node, or None when there is no node to test against. Tests return
+// early in that case, so `cargo test` passes on machines without bitcoind.
+pub fn regtest_node() -> Option<Client> {
+    let url = setting("CAPSTONE_RPC_URL", "http://127.0.0.1:18443");
+    let auth = Auth::UserPass(
+        setting("CAPSTONE_RPC_USER", "alice"),
+        setting("CAPSTONE_RPC_PASS", "password"),
+    );
+    let client = Client::new(&url, auth).ok()?;
+    match client.get_blockchain_info() {
+        Ok(info) if info.chain.to_string() == "regtest" => Some(client),
+        Ok(info) => {
+            eprintln!("skipping: node at {url} is on {}, not regtest", info.chain);
+            None
+        }
+        Err(e) => {
+            eprintln!("skipping: no regtest node at {url} ({e})");
+            None
+        }
+    }
+}
+
+// A node with no blocks yet, as the golden report assumes
+pub fn is_fresh(node: &Client) -> bool {
+    node.get_block_count()
+        .map(|height| height == 0)
+        .unwrap_or(false)
+}
+
+// Run the program with `args`, writing its report to a temporary file named after `name`.
+// Panics with the program's output if it fails; returns the report's path.
+pub fn run_flow(name: &str, args: &[&str]) -> PathBuf {
+    let out = env::temp_dir().join(format!("capstone-{name}-{}.txt", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args(args)
+        .arg("--out")
+        .arg(&out)
+        .output()
+        .expect("could not start the program");
+    assert!(
+        output.status.success(),
+        "program failed ({}):\n{}\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    out
+}
+
+// Compare a written report against a checked-in golden copy, line by line. Lines that are
+// both decimal BTC amounts compare by value (so "20" matches "20.00000000"), a golden line of
+// "*" stands for a value that differs from run to run (txids, block hashes), and everything
+// else compares as exact text. Panics with the first line that differs.
+pub fn assert_matches_golden(actual: &Path, golden: &Path) {
+    let read = |path: &Path| {
+        fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("could not read {}: {e}", path.display()))
+    };
+    let (actual_text, golden_text) = (read(actual), read(golden));
+    let (actual_lines, golden_lines): (Vec<_>, Vec<_>) =
+        (actual_text.lines().collect(), golden_text.lines().collect());
+    let amount = |line: &str| SignedAmount::from_str_in(line, Denomination::Bitcoin).ok();
+    for i in 0..actual_lines.len().max(golden_lines.len()) {
+        let (got, want) = (actual_lines.get(i), golden_lines.get(i));
+        let same = match (got, want) {
+            (Some(_), Some(&"*")) => true,
+            (Some(got), Some(want)) => match (amount(got), amount(want)) {
+                (Some(got), Some(want)) => got == want,
+                _ => got == want,
+            },
+            _ => false,
+        };
+        assert!(
+            same,
+            "{} differs from {} at line {}: expected {:?}, got {:?}",
+            actual.display(),
+            golden.display(),
+            i + 1,
+            want.unwrap_or(&"<end of file>"),
+            got.unwrap_or(&"<end of file>")
+        );
+    }
+}
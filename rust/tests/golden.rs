@@ -0,0 +1,19 @@
+mod common;
+
+use std::path::Path;
+
+// On a fresh node the seeded wallets hand out the same addresses, and a one-input send at the
+// node's fallback fee (1 sat/vB) costs the same, so everything but the txid and block hash is
+// known in advance
+#[test]
+fn seeded_run_matches_golden_report() {
+    let Some(node) = common::regtest_node() else {
+        return;
+    };
+    if !common::is_fresh(&node) {
+        eprintln!("skipping: the golden report needs a node with no blocks yet");
+        return;
+    }
+    let out = common::run_flow("golden", &["--seed", common::SEED]);
+    common::assert_matches_golden(&out, Path::new("tests/golden/seed-out.txt"));
+}